@@ -0,0 +1,9 @@
+//! `cargo bench [-- OPTIONS]`: every registered experiment through the same
+//! runner as the `cache_bench` binary, see `cache_bench --help`.
+
+use std::{env, process::ExitCode};
+
+fn main() -> ExitCode {
+    // added by cargo bench
+    cache_bench::cli::main(env::args().skip(1).filter(|arg| arg != "--bench"))
+}
@@ -0,0 +1,18 @@
+use std::{env, process::Command};
+
+/// Sets `cfg(nightly)` when built by a nightly compiler. `std::simd` and
+/// `test::Bencher` are only used under it, stable builds fall back to the
+/// portable `Cluster` in `src/simd.rs` and the in-crate harness.
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(nightly)");
+    println!("cargo::rerun-if-env-changed=RUSTC");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+        .unwrap_or_default();
+    if version.contains("-nightly") || version.contains("-dev") {
+        println!("cargo::rustc-cfg=nightly");
+    }
+}
@@ -0,0 +1,177 @@
+//! `#[derive(SoA)]` for `cache_bench`.
+//!
+//! For a struct `S` with named fields, generates `SSoA` holding one `Vec` per
+//! field, implements `cache_bench::columns::Rows` and
+//! `cache_bench::cache::Footprint` for it and `cache_bench::columns::Record`
+//! for `S`. Written against `proc_macro` only so the workspace keeps building
+//! without dependencies.
+
+use std::iter::Peekable;
+
+use proc_macro::{token_stream, Delimiter, Spacing, TokenStream, TokenTree};
+
+#[proc_macro_derive(SoA)]
+pub fn derive_soa(input: TokenStream) -> TokenStream {
+    let code = match parse_struct(input) {
+        Ok(s) => expand(&s),
+        Err(msg) => format!("compile_error!({msg:?});"),
+    };
+    code.parse().unwrap()
+}
+
+struct Field {
+    vis: String,
+    name: String,
+    ty: String,
+}
+
+struct Struct {
+    vis: String,
+    name: String,
+    fields: Vec<Field>,
+}
+
+type Tokens = Peekable<token_stream::IntoIter>;
+
+fn skip_attributes(tokens: &mut Tokens) {
+    while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#') {
+        tokens.next();
+        tokens.next();
+    }
+}
+
+fn parse_visibility(tokens: &mut Tokens) -> String {
+    match tokens.peek() {
+        Some(TokenTree::Ident(i)) if i.to_string() == "pub" => {}
+        _ => return String::new(),
+    }
+    let mut vis = tokens.next().unwrap().to_string();
+    if let Some(TokenTree::Group(g)) = tokens.peek() {
+        if g.delimiter() == Delimiter::Parenthesis {
+            vis += &tokens.next().unwrap().to_string();
+        }
+    }
+    vis
+}
+
+fn parse_struct(input: TokenStream) -> Result<Struct, String> {
+    let mut tokens = input.into_iter().peekable();
+    skip_attributes(&mut tokens);
+    let vis = parse_visibility(&mut tokens);
+    match tokens.next() {
+        Some(TokenTree::Ident(i)) if i.to_string() == "struct" => {}
+        _ => return Err("#[derive(SoA)] only supports structs".into()),
+    }
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(i)) => i.to_string(),
+        _ => return Err("expected struct name".into()),
+    };
+    let fields = match tokens.next() {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => parse_fields(g.stream())?,
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            return Err("#[derive(SoA)] does not support generic structs".into())
+        }
+        _ => return Err("#[derive(SoA)] requires a struct with named fields".into()),
+    };
+    if fields.is_empty() {
+        return Err("#[derive(SoA)] requires at least one field".into());
+    }
+    Ok(Struct { vis, name, fields })
+}
+
+fn parse_fields(body: TokenStream) -> Result<Vec<Field>, String> {
+    let mut tokens = body.into_iter().peekable();
+    let mut fields = Vec::new();
+    while tokens.peek().is_some() {
+        skip_attributes(&mut tokens);
+        let vis = parse_visibility(&mut tokens);
+        let name = match tokens.next() {
+            Some(TokenTree::Ident(i)) => i.to_string(),
+            _ => return Err("expected field name".into()),
+        };
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == ':' => {}
+            _ => return Err(format!("expected `:` after field `{name}`")),
+        }
+
+        // the type ends at the first `,` outside of `<...>`; `->` in fn
+        // pointer types must not close an angle bracket
+        let mut ty = TokenStream::new();
+        let mut depth = 0usize;
+        let mut after_dash = false;
+        for tt in tokens.by_ref() {
+            if let TokenTree::Punct(p) = &tt {
+                match p.as_char() {
+                    ',' if depth == 0 => break,
+                    '<' => depth += 1,
+                    '>' if !after_dash => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                after_dash = p.as_char() == '-' && p.spacing() == Spacing::Joint;
+            } else {
+                after_dash = false;
+            }
+            ty.extend([tt]);
+        }
+        fields.push(Field {
+            vis,
+            name,
+            ty: ty.to_string(),
+        });
+    }
+    Ok(fields)
+}
+
+fn expand(s: &Struct) -> String {
+    let Struct { vis, name, fields } = s;
+    let soa = format!("{name}SoA");
+    let mut columns = String::new();
+    let mut with_capacity = String::new();
+    let mut push = String::new();
+    let mut row = String::new();
+    let mut ranges = String::new();
+    for Field { vis, name, ty } in fields {
+        columns += &format!("{vis} {name}: ::std::vec::Vec<{ty}>,");
+        with_capacity += &format!("{name}: ::std::vec::Vec::with_capacity(n),");
+        push += &format!("self.{name}.push(row.{name});");
+        row += &format!("{name}: ::cache_bench::trace::read(&self.{name}[i]),");
+        ranges += &format!("::cache_bench::cache::Footprint::ranges(&self.{name}, out);");
+    }
+    let first = &fields[0].name;
+    format!(
+        "
+        {vis} struct {soa} {{ {columns} }}
+
+        impl ::cache_bench::columns::Rows for {soa} {{
+            type Row = {name};
+
+            fn with_capacity(n: usize) -> Self {{
+                {soa} {{ {with_capacity} }}
+            }}
+
+            fn push(&mut self, row: {name}) {{
+                {push}
+            }}
+
+            fn len(&self) -> usize {{
+                self.{first}.len()
+            }}
+
+            #[inline(always)]
+            fn row(&self, i: usize) -> {name} {{
+                {name} {{ {row} }}
+            }}
+        }}
+
+        impl ::cache_bench::cache::Footprint for {soa} {{
+            fn ranges(&self, out: &mut ::std::vec::Vec<::std::ops::Range<usize>>) {{
+                {ranges}
+            }}
+        }}
+
+        impl ::cache_bench::columns::Record for {name} {{
+            type SoA = {soa};
+        }}
+        "
+    )
+}
@@ -0,0 +1,137 @@
+//! Raw allocations with a chosen alignment, for layouts that control where
+//! their data lands relative to cache sets and pages.
+
+use std::{alloc, fmt, ops::Range, ptr::NonNull};
+
+use crate::cache::Footprint;
+
+/// `len` bytes aligned to a power of two, left uninitialized so that pages
+/// never written are never committed
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: alloc::Layout,
+}
+
+impl AlignedBuf {
+    /// Panics if `align` is not a power of two or the size overflows.
+    pub fn new(len: usize, align: usize) -> Self {
+        let layout = alloc::Layout::from_size_align(len.max(1), align)
+            .unwrap_or_else(|_| panic!("invalid buffer of {len} bytes aligned to {align}"));
+        // SAFETY: the size of layout is not zero
+        let ptr = unsafe { alloc::alloc(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        AlignedBuf { ptr, len, layout }
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        // SAFETY: allocated in new with the same layout
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+impl Footprint for AlignedBuf {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        let start = self.as_ptr() as usize;
+        out.push(start..start + self.len);
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// where the allocations of a layout start
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// wherever `Vec` puts them, aligned for a `Cluster`
+    #[default]
+    Vec,
+    /// on a cache line
+    Line,
+    /// on a page
+    Page,
+    /// 4 bytes past a cache line, every other `Cluster` straddles two lines
+    Misaligned4,
+    /// 16 bytes past a cache line, every other `Cluster` straddles two lines
+    Misaligned16,
+    /// 32 bytes past a cache line, no `Cluster` straddles but every array
+    /// starts in the middle of a line
+    Misaligned32,
+}
+
+impl Alignment {
+    pub const ALL: [Alignment; 6] = [
+        Alignment::Vec,
+        Alignment::Line,
+        Alignment::Page,
+        Alignment::Misaligned4,
+        Alignment::Misaligned16,
+        Alignment::Misaligned32,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Alignment::Vec => "vec",
+            Alignment::Line => "line",
+            Alignment::Page => "page",
+            Alignment::Misaligned4 => "off4",
+            Alignment::Misaligned16 => "off16",
+            Alignment::Misaligned32 => "off32",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == s)
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_buf() {
+        for align in [1, 64, 4096, 1 << 20] {
+            let mut buf = AlignedBuf::new(100, align);
+            assert_eq!(buf.as_ptr() as usize % align, 0);
+            assert_eq!((buf.len(), buf.align()), (100, align));
+            // SAFETY: within the 100 bytes of buf
+            unsafe {
+                buf.as_mut_ptr().add(99).write(7);
+                assert_eq!(*buf.as_ptr().add(99), 7);
+            }
+        }
+        assert!(AlignedBuf::new(0, 32).is_empty());
+        for alignment in Alignment::ALL {
+            assert_eq!(Alignment::parse(&alignment.to_string()), Some(alignment));
+        }
+        assert_eq!(Alignment::parse("off8"), None);
+    }
+}
@@ -0,0 +1,309 @@
+//! Named baselines: a saved set of [`Record`]s that later runs are compared
+//! against, benchmark by benchmark.
+
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    report::{read_jsonl, write_jsonl, Record},
+    stats::{self, Comparison},
+};
+
+/// default relative change of the median tolerated before a significant
+/// slowdown counts as a regression
+pub const THRESHOLD: f64 = 0.05;
+
+/// environment variable naming the directory baselines are kept in
+pub const DIR_VAR: &str = "CACHE_BENCH_BASELINES";
+
+/// `$CACHE_BENCH_BASELINES`, else `cache_bench/baselines` in
+/// `$CARGO_TARGET_DIR` or `target/` of the current directory
+pub fn default_dir() -> PathBuf {
+    if let Some(dir) = env::var_os(DIR_VAR) {
+        return PathBuf::from(dir);
+    }
+    env::var_os("CARGO_TARGET_DIR")
+        .map_or_else(|| PathBuf::from("target"), PathBuf::from)
+        .join("cache_bench/baselines")
+}
+
+/// Fails on names that are empty, hidden or would leave `dir`.
+pub fn path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid baseline name {name:?}"),
+        ));
+    }
+    Ok(dir.join(format!("{name}.jsonl")))
+}
+
+/// Saves `records` as baseline `name` in `dir`, replacing an older one.
+pub fn save(dir: &Path, name: &str, records: &[Record]) -> io::Result<PathBuf> {
+    let path = path(dir, name)?;
+    fs::create_dir_all(dir)?;
+    write_jsonl(&mut File::create(&path)?, records)?;
+    Ok(path)
+}
+
+pub fn load(dir: &Path, name: &str) -> io::Result<Vec<Record>> {
+    let path = path(dir, name)?;
+    let file = File::open(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    read_jsonl(BufReader::new(file))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// no significant change beyond the threshold
+    Unchanged,
+    /// significantly faster by more than the threshold
+    Improved,
+    /// significantly slower by more than the threshold
+    Regressed,
+    /// not in the baseline
+    New,
+    /// in the baseline, but not measured in this run
+    Missing,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Unchanged => "ok",
+            Verdict::Improved => "improved",
+            Verdict::Regressed => "REGRESSED",
+            Verdict::New => "new",
+            Verdict::Missing => "missing",
+        })
+    }
+}
+
+/// one benchmark of the current run against the baseline
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub name: String,
+    pub n: usize,
+    pub m: usize,
+    /// median in ns of the baseline, if it has this benchmark
+    pub baseline: Option<f64>,
+    /// median in ns of the current run, if it has this benchmark
+    pub current: Option<f64>,
+    /// current against baseline, if both exist
+    pub comparison: Option<Comparison>,
+    pub verdict: Verdict,
+}
+
+impl Change {
+    /// relative change of the median, `0.1` is 10% slower
+    pub fn relative(&self) -> Option<f64> {
+        Some(self.current? / self.baseline? - 1.0)
+    }
+}
+
+pub struct Regressions {
+    pub threshold: f64,
+    pub changes: Vec<Change>,
+}
+
+impl Regressions {
+    /// true unless some benchmark regressed
+    pub fn passed(&self) -> bool {
+        self.count(Verdict::Regressed) == 0
+    }
+
+    pub fn count(&self, verdict: Verdict) -> usize {
+        self.changes.iter().filter(|c| c.verdict == verdict).count()
+    }
+}
+
+impl fmt::Display for Regressions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<40} {:>6} {:>5} {:>12} {:>12} {:>8}  {:<25} verdict",
+            "benchmark", "n", "m", "baseline ns", "current ns", "change", "significance"
+        )?;
+        let ns = |x: Option<f64>| x.map_or("-".to_string(), |x| format!("{x:.1}"));
+        for c in &self.changes {
+            writeln!(
+                f,
+                "{:<40} {:>6} {:>5} {:>12} {:>12} {:>8}  {:<25} {}",
+                c.name,
+                c.n,
+                c.m,
+                ns(c.baseline),
+                ns(c.current),
+                c.relative()
+                    .map_or("-".to_string(), |r| format!("{:+.1}%", r * 100.0)),
+                c.comparison.map_or("-".to_string(), |c| c.to_string()),
+                c.verdict
+            )?;
+        }
+        write!(
+            f,
+            "{}: {} regressed, {} improved, {} unchanged, {} new, {} missing (threshold {:.1}%)",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.count(Verdict::Regressed),
+            self.count(Verdict::Improved),
+            self.count(Verdict::Unchanged),
+            self.count(Verdict::New),
+            self.count(Verdict::Missing),
+            self.threshold * 100.0
+        )
+    }
+}
+
+/// Compares every benchmark of `current` with the one of the same name, `n`,
+/// `m`, rotation, cache mode and SIMD backend in `baseline`. A change only
+/// counts if the confidence intervals of the medians are disjoint and the
+/// medians differ by more than `threshold`.
+pub fn compare(baseline: &[Record], current: &[Record], threshold: f64) -> Regressions {
+    let key = |r: &Record| {
+        let meta = &r.meta;
+        (
+            meta.name.clone(),
+            meta.n,
+            meta.m,
+            meta.rotation.clone(),
+            meta.cache.clone(),
+            meta.simd.clone(),
+        )
+    };
+    let mut changes: Vec<Change> = current
+        .iter()
+        .map(|cur| {
+            let base = baseline.iter().find(|b| key(b) == key(cur));
+            let comparison = base.map(|base| stats::compare(&cur.summary, &base.summary));
+            let ratio = base.map(|base| cur.summary.median / base.summary.median);
+            let verdict = match (comparison, ratio) {
+                (None, _) | (_, None) => Verdict::New,
+                (Some(Comparison::Slower { .. }), Some(r)) if r > 1.0 + threshold => {
+                    Verdict::Regressed
+                }
+                (Some(Comparison::Faster { .. }), Some(r)) if r < 1.0 - threshold => {
+                    Verdict::Improved
+                }
+                _ => Verdict::Unchanged,
+            };
+            Change {
+                name: cur.meta.name.clone(),
+                n: cur.meta.n,
+                m: cur.meta.m,
+                baseline: base.map(|b| b.summary.median),
+                current: Some(cur.summary.median),
+                comparison,
+                verdict,
+            }
+        })
+        .collect();
+    for base in baseline {
+        if !current.iter().any(|cur| key(cur) == key(base)) {
+            changes.push(Change {
+                name: base.meta.name.clone(),
+                n: base.meta.n,
+                m: base.meta.m,
+                baseline: Some(base.summary.median),
+                current: None,
+                comparison: None,
+                verdict: Verdict::Missing,
+            });
+        }
+    }
+    Regressions { threshold, changes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{harness::Measurement, report::Meta};
+
+    fn record(name: &str, base: f64) -> Record {
+        let meta = Meta::kernel("bench_runtime", name, "soa", 5, &[0], 256, 300);
+        let samples = (0..50).map(|i| base + (i % 10) as f64).collect();
+        let measurement = Measurement {
+            iters: 1,
+            samples,
+            counters: Vec::new(),
+        };
+        Record::new(meta, &measurement)
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = [
+            record("same", 100.0),
+            record("slower", 100.0),
+            record("slightly_slower", 100.0),
+            record("faster", 100.0),
+            record("gone", 100.0),
+        ];
+        let current = [
+            record("same", 100.0),
+            record("slower", 120.0),
+            record("slightly_slower", 105.0),
+            record("faster", 80.0),
+            record("added", 100.0),
+        ];
+        let regressions = compare(&baseline, &current, THRESHOLD);
+        let verdicts: Vec<_> = regressions
+            .changes
+            .iter()
+            .map(|c| (c.name.as_str(), c.verdict))
+            .collect();
+        assert_eq!(
+            verdicts,
+            [
+                ("same", Verdict::Unchanged),
+                ("slower", Verdict::Regressed),
+                ("slightly_slower", Verdict::Unchanged),
+                ("faster", Verdict::Improved),
+                ("added", Verdict::New),
+                ("gone", Verdict::Missing),
+            ]
+        );
+        assert!(matches!(
+            regressions.changes[2].comparison,
+            Some(Comparison::Slower { .. })
+        ));
+        assert!(!regressions.passed());
+        assert!(compare(&baseline, &current, 0.25).passed());
+        assert!(regressions.to_string().ends_with(
+            "FAIL: 1 regressed, 1 improved, 2 unchanged, 1 new, 1 missing (threshold 5.0%)"
+        ));
+
+        // never against a build with another SIMD backend
+        let mut other = record("slower", 120.0);
+        other.meta.simd = "other".to_string();
+        let regressions = compare(&baseline[1..2], &[other], THRESHOLD);
+        assert_eq!(
+            (
+                regressions.count(Verdict::New),
+                regressions.count(Verdict::Missing)
+            ),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn test_save_load() {
+        let dir = env::temp_dir().join(format!("cache_bench_baseline_{}", std::process::id()));
+        let records = vec![record("a", 100.0), record("b", 200.0)];
+        let path = save(&dir, "main", &records).unwrap();
+        assert_eq!(path, dir.join("main.jsonl"));
+        let loaded = load(&dir, "main");
+        let missing = load(&dir, "other");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), records);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        for name in ["", "../main", "a/b", "a\\b", "..", ".hidden"] {
+            let err = save(&dir, name, &records).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(!dir.exists());
+    }
+}
@@ -1,217 +1,301 @@
-#[cfg(test)]
-pub mod tests {
-    use std::{
-        env::Args,
-        simd::{num::SimdFloat, StdFloat},
-        slice,
-    };
+use std::slice;
+
+use crate::registry::Experiment;
+use crate::report::Meta;
+use crate::roofline::Ops;
+use crate::simd::{SimdFloat, StdFloat};
+use crate::trace::{read, write};
+use crate::utils::{make_data, make_placed_data, ArgsIn, ArgsOut, Cluster, Columns, M, N, PAGE};
+
+#[inline]
+fn make_splat_slice(var: &Cluster) -> (&[Cluster], usize) {
+    let ptr = var as *const Cluster;
+    (unsafe { slice::from_raw_parts(ptr, 1) }, 0)
+}
 
-    use test::{black_box, Bencher};
+// ----------------------------------------------------------------------------
+
+fn f_singleindexing(args_in: &ArgsIn, args_out: &mut ArgsOut) {
+    let a = args_in[0].0;
+    let c = args_in[1].0;
+    let d = args_in[2].0;
+    let e = args_in[3].0;
+    let b = &mut *args_out[0].0;
+    let len = a.len();
+    let mut sum = Cluster::splat(0.0);
+    for i in 0..len {
+        let tmp = read(&a[i]).mul_add(read(&c[i]).sqrt(), read(&d[i]) / read(&e[i]));
+        sum += tmp;
+        write(&mut b[i], sum);
+    }
+}
 
-    use crate::utils::{bench, make_data, make_datasets, ArgsIn, ArgsOut, Cluster, Data, N};
+fn runtime<D: Columns>(data_in: &D, data_out: &mut D, f: fn(&ArgsIn, &mut ArgsOut)) -> f32 {
+    let mut args_in = ArgsIn::default();
+    args_in[0] = (data_in.a(), 1);
+    args_in[1] = (data_in.c(), 1);
+    args_in[2] = (data_in.d(), 1);
+    args_in[3] = (data_in.e(), 1);
 
-    #[inline]
-    fn make_splat_slice(var: &Cluster) -> (&[Cluster], usize) {
-        let ptr = var as *const Cluster;
-        (unsafe { &slice::from_raw_parts(ptr, 1) }, 0)
-    }
+    let mut args_out = ArgsOut::default();
+    args_out[0] = (data_out.b_mut(), 1);
 
-    // ------------------------------------------------------------------------
-
-    fn f_singleindexing(args_in: &ArgsIn, args_out: &mut ArgsOut) {
-        let a = args_in[0].0;
-        let c = args_in[1].0;
-        let d = args_in[2].0;
-        let e = args_in[3].0;
-        let b = args_out[0].0.as_mut();
-        let len = a.len();
-        let mut sum = Cluster::splat(0.0);
-        for i in 0..len {
-            let tmp = a[i].mul_add(c[i].sqrt(), d[i] / e[i]);
-            sum += tmp;
-            b[i] = sum;
-        }
-    }
+    f(&args_in, &mut args_out);
 
-    fn runtime(data_in: &Data, data_out: &mut Data, f: fn(&ArgsIn, &mut ArgsOut)) -> f32 {
-        let mut args_in = ArgsIn::default();
-        args_in[0] = (&data_in.a, 1);
-        args_in[1] = (&data_in.c, 1);
-        args_in[2] = (&data_in.d, 1);
-        args_in[3] = (&data_in.e, 1);
+    args_out[0].0.last().unwrap().reduce_sum()
+}
 
-        let mut args_out = ArgsOut::default();
-        args_out[0] = (&mut data_out.b, 1);
+// ----------------------------------------------------------------------------
 
-        f(&args_in, &mut args_out);
+fn runtime_with_const_memory<D: Columns>(
+    data_sets_in: &[D],
+    data_in: &D,
+    data_out: &mut D,
+    f: fn(&ArgsIn, &mut ArgsOut),
+) -> f32 {
+    let mut args_in = ArgsIn::default();
+    args_in[0] = (data_sets_in[0].a(), 1);
+    args_in[1] = (data_sets_in[0].c(), 1);
+    args_in[2] = (data_in.d(), 1);
+    args_in[3] = (data_in.e(), 1);
 
-        args_out[0].0.last().unwrap().reduce_sum()
-    }
+    let mut args_out = ArgsOut::default();
+    args_out[0] = (data_out.b_mut(), 1);
 
-    #[bench]
-    fn bench_runtime_singleindexing(b: &mut Bencher) {
-        let data_in = make_datasets(|_| (make_data()));
-        let mut data_out = make_datasets(|_| (make_data()));
-        bench(b, &data_in, &mut data_out, |d_in, d_out| {
-            black_box(runtime(d_in, d_out, f_singleindexing));
-        });
-    }
-    // ------------------------------------------------------------------------
-
-    fn runtime_with_const_memory(
-        data_sets_in: &Vec<Data>,
-        data_in: &Data,
-        data_out: &mut Data,
-        f: fn(&ArgsIn, &mut ArgsOut),
-    ) -> f32 {
-        let mut args_in = ArgsIn::default();
-        args_in[0] = (&*data_sets_in[0].a, 1);
-        args_in[1] = (&*data_sets_in[0].c, 1);
-        args_in[2] = (&data_in.d, 1);
-        args_in[3] = (&data_in.e, 1);
-
-        let mut args_out = ArgsOut::default();
-        args_out[0] = (&mut data_out.b, 1);
-
-        f(&args_in, &mut args_out);
-
-        args_out[0].0.last().unwrap().reduce_sum()
-    }
+    f(&args_in, &mut args_out);
 
-    #[bench]
-    fn bench_runtime_singleindexing_with_const_memory(b: &mut Bencher) {
-        let data_sets_in = make_datasets(|_| (make_data()));
-        let mut data_sets_out = make_datasets(|_| (make_data()));
-
-        bench(b, &data_sets_in, &mut data_sets_out, |d_in, d_out| {
-            black_box(runtime_with_const_memory(
-                &data_sets_in,
-                d_in,
-                d_out,
-                f_singleindexing,
-            ));
-        });
-    }
+    args_out[0].0.last().unwrap().reduce_sum()
+}
 
-    // ------------------------------------------------------------------------
-
-    fn f_multiindexing(args_in: &ArgsIn, args_out: &mut ArgsOut) {
-        let a = args_in[0].0;
-        let c = args_in[1].0;
-        let d = args_in[2].0;
-        let e = args_in[3].0;
-        let b = args_out[0].0.as_mut();
-
-        let len = b.len();
-        let mut ia = 0;
-        let mut ic = 0;
-        let mut id = 0;
-        let mut ib = 0;
-        let mut ie = 0;
-
-        let mut sum = Cluster::splat(0.0);
-
-        while ib < len {
-            let tmp = a[ia].mul_add(c[ic].sqrt(), d[id] / e[ie]);
-            sum += tmp;
-            b[ib] = sum;
-            ia += args_in[0].1;
-            ic += args_in[1].1;
-            id += args_in[2].1;
-            ie += args_in[3].1;
-            ib += args_out[0].1;
-        }
+// ----------------------------------------------------------------------------
+
+pub(crate) fn f_multiindexing(args_in: &ArgsIn, args_out: &mut ArgsOut) {
+    let a = args_in[0].0;
+    let c = args_in[1].0;
+    let d = args_in[2].0;
+    let e = args_in[3].0;
+    let b = &mut *args_out[0].0;
+
+    let len = b.len();
+    let mut ia = 0;
+    let mut ic = 0;
+    let mut id = 0;
+    let mut ib = 0;
+    let mut ie = 0;
+
+    let mut sum = Cluster::splat(0.0);
+
+    while ib < len {
+        let tmp = read(&a[ia]).mul_add(read(&c[ic]).sqrt(), read(&d[id]) / read(&e[ie]));
+        sum += tmp;
+        write(&mut b[ib], sum);
+        ia += args_in[0].1;
+        ic += args_in[1].1;
+        id += args_in[2].1;
+        ie += args_in[3].1;
+        ib += args_out[0].1;
     }
+}
 
-    fn runtime_with_splats(
-        data_in: &Data,
-        data_out: &mut Data,
-        f: fn(&ArgsIn, &mut ArgsOut),
-    ) -> f32 {
-        let mut args_in = ArgsIn::default();
-        let a = Cluster::splat(4.0);
-        let c = Cluster::splat(3.0);
-        args_in[0] = make_splat_slice(&a);
-        //args_in[0] = (&data_in.a, 1);
-        args_in[1] = make_splat_slice(&c);
-        args_in[2] = (&data_in.d, 1);
-        args_in[3] = (&data_in.e, 1);
-
-        let mut args_out = ArgsOut::default();
-        args_out[0] = (&mut data_out.b, 1);
-
-        f(&args_in, &mut args_out);
-        args_out[0].0.last().unwrap().reduce_sum()
-    }
+fn runtime_with_splats<D: Columns>(
+    data_in: &D,
+    data_out: &mut D,
+    f: fn(&ArgsIn, &mut ArgsOut),
+) -> f32 {
+    let mut args_in = ArgsIn::default();
+    let a = Cluster::splat(4.0);
+    let c = Cluster::splat(3.0);
+    args_in[0] = make_splat_slice(&a);
+    args_in[1] = make_splat_slice(&c);
+    args_in[2] = (data_in.d(), 1);
+    args_in[3] = (data_in.e(), 1);
+
+    let mut args_out = ArgsOut::default();
+    args_out[0] = (data_out.b_mut(), 1);
+
+    f(&args_in, &mut args_out);
+    args_out[0].0.last().unwrap().reduce_sum()
+}
 
-    #[bench]
-    fn bench_runtime_multiindexing_with_splats(b: &mut Bencher) {
-        let data_in = make_datasets(|_| (make_data()));
-        let mut data_out = make_datasets(|_| (make_data()));
-        bench(b, &data_in, &mut data_out, |d_in, d_out| {
-            black_box(runtime_with_splats(d_in, d_out, f_multiindexing));
-        });
+// ----------------------------------------------------------------------------
+
+fn runtime_with_memory_splats<D: Columns>(
+    data_in: &D,
+    data_out: &mut D,
+    mem1: &mut [Cluster],
+    mem2: &mut [Cluster],
+    f: fn(&ArgsIn, &mut ArgsOut),
+) -> f32 {
+    let mut args_in = ArgsIn::default();
+    let a = Cluster::splat(4.0);
+    let c = Cluster::splat(3.0);
+    for i in 0..N {
+        write(&mut mem1[i], a);
+        write(&mut mem2[i], c);
     }
+    args_in[0] = (mem1, 1);
+    args_in[1] = (mem2, 1);
+    args_in[2] = (data_in.d(), 1);
+    args_in[3] = (data_in.e(), 1);
 
-    // ------------------------------------------------------------------------
+    let mut args_out = ArgsOut::default();
+    args_out[0] = (data_out.b_mut(), 1);
 
-    #[bench]
-    fn bench_runtime_multiindexing(b: &mut Bencher) {
-        let data_sets_in = make_datasets(|_| (make_data()));
-        let mut data_sets_out = make_datasets(|_| (make_data()));
+    f(&args_in, &mut args_out);
+    args_out[0].0.last().unwrap().reduce_sum()
+}
 
-        bench(b, &data_sets_in, &mut data_sets_out, |d_in, d_out| {
-            black_box(runtime(d_in, d_out, f_multiindexing));
-        });
-    }
+// ----------------------------------------------------------------------------
+
+/// `a.mul_add(c.sqrt(), d / e)` and the running sum, in every kernel above
+pub(crate) const OPS: Ops = Ops {
+    add: 1,
+    mul_add: 1,
+    sqrt: 1,
+    div: 1,
+    ..Ops::NONE
+};
+
+/// reads the `touched` fields and writes `b`
+fn meta(name: &str, touched: &[usize]) -> Meta {
+    Meta::kernel("bench_runtime", name, "soa", 5, touched, N, M)
+        .with_bytes(touched.len() * size_of::<Cluster>(), size_of::<Cluster>())
+        .with_ops(OPS)
+        .with_tag("runtime")
+}
 
-    // ------------------------------------------------------------------------
-    fn runtime_with_memory_splats(
-        data_in: &Data,
-        data_out: &mut Data,
-        mem1: &mut [Cluster],
-        mem2: &mut [Cluster],
-        f: fn(&ArgsIn, &mut ArgsOut),
-    ) -> f32 {
-        let mut args_in = ArgsIn::default();
-        let a = Cluster::splat(4.0);
-        let c = Cluster::splat(3.0);
-        for i in 0..N {
-            mem1[i] = a;
-            mem2[i] = c;
-        }
-        args_in[0] = (&mem1, 1);
-        args_in[1] = (&mem2, 1);
-        args_in[2] = (&data_in.d, 1);
-        args_in[3] = (&data_in.e, 1);
+/// where the output datasets of the runtime kernels lie relative to the
+/// inputs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Placement {
+    /// every column its own `Vec`, wherever the allocator puts it
+    #[default]
+    Vec,
+    /// Page-aligned input columns and output columns starting this many
+    /// bytes past a page boundary. Every store to `b[i]` then shares its
+    /// address bits below 4K with the loads of element `i + distance / 32`,
+    /// which the CPU may take for a dependency: 4K aliasing, or a false
+    /// store-to-load forwarding.
+    Distance(usize),
+}
 
-        let mut args_out = ArgsOut::default();
-        args_out[0] = (&mut data_out.b, 1);
+impl Placement {
+    /// the name suffix, empty for [`Placement::Vec`]
+    pub fn variant(self) -> String {
+        match self {
+            Placement::Vec => String::new(),
+            Placement::Distance(distance) => format!("alias_{distance}"),
+        }
+    }
+}
 
-        f(&args_in, &mut args_out);
-        args_out[0].0.last().unwrap().reduce_sum()
+/// distances of the output columns past a page boundary registered: every
+/// element up to 8 ahead, then far ahead and one behind
+pub const DISTANCES: [usize; 13] = [
+    0,
+    32,
+    64,
+    96,
+    128,
+    160,
+    192,
+    224,
+    256,
+    1024,
+    2048,
+    3072,
+    PAGE - size_of::<Cluster>(),
+];
+
+/// the kernels above over [`Data`], which reads `a`, `c`, `d`, `e` (fields
+/// 0, 2, 3, 4) and writes `b`, with the allocator's placement and at every
+/// one of [`DISTANCES`]
+pub fn experiments() -> Vec<Experiment> {
+    let mut out = experiments_with(Placement::Vec);
+    for distance in DISTANCES {
+        out.extend(experiments_with(Placement::Distance(distance)));
     }
+    out
+}
 
-    #[bench]
-    fn bench_runtime_singleindexing_with_memory_splats(b: &mut Bencher) {
-        let data_in = make_datasets(|_| (make_data()));
-        let mut data_out = make_datasets(|_| (make_data()));
-        let mut mem1 = vec![Cluster::splat(0.0); N];
-        let mut mem2 = vec![Cluster::splat(0.0); N];
-        bench(b, &data_in, &mut data_out, |d_in, d_out| {
-            black_box(runtime_with_memory_splats(
-                d_in,
-                d_out,
-                &mut mem1,
-                &mut mem2,
-                f_singleindexing,
-            ));
-        });
+/// the kernels above with their datasets placed by `placement`
+pub fn experiments_with(placement: Placement) -> Vec<Experiment> {
+    match placement {
+        Placement::Vec => kernels(placement, make_data, make_data),
+        Placement::Distance(distance) => kernels(
+            placement,
+            || make_placed_data(0),
+            move || make_placed_data(distance),
+        ),
     }
+}
+
+fn kernels<D: Columns + 'static>(
+    placement: Placement,
+    make_in: impl Fn() -> D + Clone + 'static,
+    make_out: impl Fn() -> D + Clone + 'static,
+) -> Vec<Experiment> {
+    let meta = |name: &str, touched: &[usize]| match placement {
+        Placement::Vec => meta(name, touched),
+        Placement::Distance(_) => meta(name, touched)
+            .with_variant(&placement.variant())
+            .with_tag("alias"),
+    };
+    vec![
+        Experiment::data_with(
+            meta("runtime_singleindexing", &[0, 2, 3, 4]),
+            make_in.clone(),
+            make_out.clone(),
+            || |_, d_in, d_out| runtime(d_in, d_out, f_singleindexing),
+        ),
+        Experiment::data_with(
+            meta("runtime_singleindexing_with_const_memory", &[0, 2, 3, 4]),
+            make_in.clone(),
+            make_out.clone(),
+            || {
+                |data_sets_in, d_in, d_out| {
+                    runtime_with_const_memory(data_sets_in, d_in, d_out, f_singleindexing)
+                }
+            },
+        ),
+        Experiment::data_with(
+            meta("runtime_multiindexing_with_splats", &[3, 4]).with_tag("splats"),
+            make_in.clone(),
+            make_out.clone(),
+            || |_, d_in, d_out| runtime_with_splats(d_in, d_out, f_multiindexing),
+        ),
+        Experiment::data_with(
+            meta("runtime_multiindexing", &[0, 2, 3, 4]),
+            make_in.clone(),
+            make_out.clone(),
+            || |_, d_in, d_out| runtime(d_in, d_out, f_multiindexing),
+        ),
+        Experiment::data_with(
+            // also writes and reads back the two splat columns
+            meta("runtime_singleindexing_with_memory_splats", &[3, 4])
+                .with_bytes(4 * size_of::<Cluster>(), 3 * size_of::<Cluster>())
+                .with_tag("splats"),
+            make_in,
+            make_out,
+            || {
+                let mut mem1 = vec![Cluster::splat(0.0); N];
+                let mut mem2 = vec![Cluster::splat(0.0); N];
+                move |_, d_in, d_out| {
+                    runtime_with_memory_splats(d_in, d_out, &mut mem1, &mut mem2, f_singleindexing)
+                }
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{make_data, make_datasets};
 
     #[test]
     fn test_runtime() {
-        let data_in = make_datasets(|_| (make_data()));
+        let data_in = make_datasets(|_| make_data());
         let mut data_out = make_data();
         let res1 = runtime(&data_in[0], &mut data_out, f_singleindexing);
         let res2 = runtime(&data_in[0], &mut data_out, f_multiindexing);
@@ -229,5 +313,32 @@ pub mod tests {
         assert_eq!(res1, res3);
         assert_eq!(res1, res4);
         assert_eq!(res1, res5);
+
+        for distance in [0, 32, PAGE - 32] {
+            let placed_in = make_placed_data(0);
+            let mut placed_out = make_placed_data(distance);
+            let b = placed_out.b_mut().as_ptr() as usize;
+            assert_eq!(placed_in.a().as_ptr() as usize % PAGE, 0);
+            assert_eq!(placed_in.e().as_ptr() as usize % PAGE, 0);
+            assert_eq!(b % PAGE, distance);
+            assert_eq!(runtime(&placed_in, &mut placed_out, f_singleindexing), res1);
+            assert_eq!(
+                runtime_with_splats(&placed_in, &mut placed_out, f_multiindexing),
+                res1
+            );
+        }
+    }
+
+    #[test]
+    fn test_placement() {
+        let placed = experiments_with(Placement::Distance(64));
+        assert_eq!(placed.len(), 5);
+        assert_eq!(placed[0].meta.name, "runtime_singleindexing_alias_64");
+        assert!(placed.iter().all(|e| e.meta.has_tag("alias")));
+        assert_eq!(
+            experiments_with(Placement::Vec)[0].meta.name,
+            "runtime_singleindexing"
+        );
+        assert_eq!(experiments().len(), 5 * (1 + DISTANCES.len()));
     }
 }
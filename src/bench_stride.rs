@@ -0,0 +1,151 @@
+//! `f_multiindexing` and `f_pointer_arithmetics` over one `Cluster` array
+//! read with a stride: every step from 1 to 64 elements, then power-of-two
+//! strides up to 64K bytes. The four inputs are consecutive regions of the
+//! array, each `N` strides long so that they never share a line, the output
+//! is contiguous.
+//!
+//! Run hot to see cache-set conflicts, strides of whole pages mapping every
+//! load to the same sets, and cold to see where the prefetchers stop
+//! following the stream.
+
+use std::slice;
+
+use crate::bench_pointer_arithmetic::f_pointer_arithmetics;
+use crate::bench_runtime::{f_multiindexing, OPS};
+use crate::cache::footprint;
+use crate::harness;
+use crate::registry::Experiment;
+use crate::report::Meta;
+use crate::simd::SimdFloat;
+use crate::utils::{ArgsIn, ArgsOut, Cluster, N};
+
+/// input steps in elements: 1 to 64, then powers of two up to 64K bytes
+pub fn steps() -> Vec<usize> {
+    let pow2 = (7..=11).map(|k| 1 << k);
+    (1..=64).chain(pow2).collect()
+}
+
+/// four inputs of `N` elements read `step` apart, each long enough for the
+/// pointers of `f_pointer_arithmetics` to end one past it
+fn make_input(step: usize) -> Vec<Cluster> {
+    vec![Cluster::splat(2.0); 4 * N * step]
+}
+
+fn multiindexing(x: &[Cluster], out: &mut [Cluster], step: usize) -> f32 {
+    let mut args_in = ArgsIn::default();
+    for (arg, input) in args_in[..4].iter_mut().zip(x.chunks(x.len() / 4)) {
+        *arg = (input, step);
+    }
+    let mut args_out = ArgsOut::default();
+    args_out[0] = (out, 1);
+
+    f_multiindexing(&args_in, &mut args_out);
+
+    args_out[0].0.last().unwrap().reduce_sum()
+}
+
+fn ptr_arithmetics(x: &[Cluster], out: &mut [Cluster], step: usize) -> f32 {
+    let len = x.len() / 4;
+    assert!(out.len() * step <= len);
+    let [x0, x1, x2, x3] = [0, 1, 2, 3].map(|k| x[k * len..].as_ptr());
+    let stride = step * size_of::<Cluster>();
+
+    f_pointer_arithmetics(
+        x0,
+        x1,
+        x2,
+        x3,
+        out.as_mut_ptr(),
+        stride,
+        stride,
+        stride,
+        stride,
+        size_of::<Cluster>(),
+        out.len(),
+    );
+
+    out.last().unwrap().reduce_sum()
+}
+
+// ----------------------------------------------------------------------------
+
+/// reads four `Cluster`s and writes one per element, whatever the stride
+fn experiment(
+    name: &str,
+    step: usize,
+    kernel: fn(&[Cluster], &mut [Cluster], usize) -> f32,
+) -> Experiment {
+    let stride = step * size_of::<Cluster>();
+    let mut meta = Meta::kernel("bench_stride", name, "strided", 1, &[0], N, 1)
+        .with_bytes(4 * size_of::<Cluster>(), size_of::<Cluster>())
+        .with_ops(OPS)
+        .with_variant(&stride.to_string())
+        .with_tag("stride");
+    if stride.is_power_of_two() {
+        meta = meta.with_tag("pow2");
+    }
+    Experiment {
+        meta,
+        run: Box::new(move |config| {
+            let x = make_input(step);
+            let mut out = vec![Cluster::splat(0.0); N];
+            let output = footprint(&out);
+            harness::run_on(config, slice::from_ref(&x), &output, |x| {
+                kernel(x, &mut out, step)
+            })
+        }),
+        trace: Box::new(move |config| {
+            let x = make_input(step);
+            let mut out = vec![Cluster::splat(0.0); N];
+            harness::trace_on(config, slice::from_ref(&x), |x| kernel(x, &mut out, step))
+        }),
+    }
+}
+
+/// both kernels at every step, named by the stride in bytes:
+/// `stride_multiindexing_4096`
+pub fn experiments() -> Vec<Experiment> {
+    let mut out = Vec::new();
+    for step in steps() {
+        out.push(experiment("stride_multiindexing", step, multiindexing));
+        out.push(experiment("stride_ptr_arithmetics", step, ptr_arithmetics));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::{capture, enabled};
+
+    #[test]
+    fn test_stride() {
+        let steps = steps();
+        assert_eq!(steps.len(), 69);
+        assert_eq!(steps.last(), Some(&((64 << 10) / size_of::<Cluster>())));
+        for step in [1, 3, 64, 2048] {
+            let x = make_input(step);
+            let mut out1 = vec![Cluster::splat(0.0); N];
+            let mut out2 = vec![Cluster::splat(0.0); N];
+            let (sum1, trace1) = capture(|| multiindexing(&x, &mut out1, step));
+            let (sum2, trace2) = capture(|| ptr_arithmetics(&x, &mut out2, step));
+            assert_eq!(sum1, sum2);
+            assert_eq!(out1, out2);
+            assert_eq!(trace1.len(), trace2.len());
+            if enabled() {
+                // one load from each input, then the store
+                let stride = step * size_of::<Cluster>();
+                assert_eq!(trace1[1].addr - trace1[0].addr, N * stride);
+                assert_eq!(trace2[3].addr - trace2[0].addr, 3 * N * stride);
+                assert_eq!(trace1[5].addr - trace1[0].addr, stride);
+                assert_eq!(trace2[5].addr - trace2[0].addr, stride);
+            }
+        }
+        let experiments = experiments();
+        assert!(experiments.iter().all(|e| e.meta.layout == "strided"));
+        assert_eq!(
+            experiments[1].meta.variant,
+            size_of::<Cluster>().to_string()
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Cache state a benchmark is measured in, and evicting the caches for the
+//! cold one.
+//!
+//! Rotating over many datasets only makes the next one likely to be out of
+//! cache. [`CacheMode::Cold`] instead evicts before every timed iteration,
+//! with `clflush` over the memory the iteration touches or by sweeping a
+//! buffer larger than the last level cache.
+
+use std::{fmt, hint::black_box, ops::Range};
+
+use crate::topology::CacheTopology;
+
+/// the cache state every timed iteration starts in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// every iteration on the same dataset, which stays in cache
+    Hot,
+    /// iterations rotate over the datasets as configured, the default
+    #[default]
+    Warm,
+    /// caches evicted before every iteration, which is timed on its own
+    Cold(Eviction),
+}
+
+impl CacheMode {
+    /// `hot`, `warm`, `cold` or `cold:EVICTION`, as written by `Display`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once(':') {
+            None if s == "hot" => Some(CacheMode::Hot),
+            None if s == "warm" => Some(CacheMode::Warm),
+            None if s == "cold" => Some(CacheMode::Cold(Eviction::default())),
+            Some(("cold", eviction)) => Eviction::parse(eviction).map(CacheMode::Cold),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheMode::Hot => f.write_str("hot"),
+            CacheMode::Warm => f.write_str("warm"),
+            CacheMode::Cold(eviction) => write!(f, "cold:{eviction}"),
+        }
+    }
+}
+
+/// how [`CacheMode::Cold`] evicts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    /// `clflush` every line the next iteration touches, x86_64 only
+    Clflush,
+    /// read and write a buffer of twice the last level cache
+    Sweep,
+}
+
+/// `clflush` where available, it is much faster than a sweep
+impl Default for Eviction {
+    fn default() -> Self {
+        if Eviction::Clflush.is_supported() {
+            Eviction::Clflush
+        } else {
+            Eviction::Sweep
+        }
+    }
+}
+
+impl Eviction {
+    pub fn is_supported(self) -> bool {
+        match self {
+            Eviction::Clflush => cfg!(target_arch = "x86_64"),
+            Eviction::Sweep => true,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "clflush" => Some(Eviction::Clflush),
+            "sweep" => Some(Eviction::Sweep),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Eviction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Eviction::Clflush => "clflush",
+            Eviction::Sweep => "sweep",
+        })
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// The memory a dataset occupies, as address ranges, so that it can be
+/// flushed from the caches.
+pub trait Footprint {
+    /// appends the address range of every allocation
+    fn ranges(&self, out: &mut Vec<Range<usize>>);
+}
+
+impl<T> Footprint for [T] {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        let start = self.as_ptr() as usize;
+        out.push(start..start + size_of_val(self));
+    }
+}
+
+impl<T> Footprint for Vec<T> {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        self.as_slice().ranges(out);
+    }
+}
+
+/// the address ranges of `value`
+pub fn footprint<T: Footprint + ?Sized>(value: &T) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    value.ranges(&mut out);
+    out
+}
+
+// ----------------------------------------------------------------------------
+
+pub struct Evictor {
+    eviction: Eviction,
+    line_size: usize,
+    /// for [`Eviction::Sweep`], empty otherwise
+    buffer: Vec<u8>,
+}
+
+impl Evictor {
+    /// Falls back to [`Eviction::Sweep`] where `clflush` is not supported.
+    /// The sweep buffer is allocated and touched here, not on every eviction.
+    pub fn new(eviction: Eviction, topology: &CacheTopology) -> Self {
+        let eviction = if eviction.is_supported() {
+            eviction
+        } else {
+            Eviction::Sweep
+        };
+        let buffer = match eviction {
+            Eviction::Clflush => Vec::new(),
+            Eviction::Sweep => vec![1; 2 * topology.last_level().map_or(0, |c| c.size)],
+        };
+        Evictor {
+            eviction,
+            line_size: topology.line_size(),
+            buffer,
+        }
+    }
+
+    pub fn eviction(&self) -> Eviction {
+        self.eviction
+    }
+
+    /// Evicts `ranges` from every cache level, or everything for a sweep.
+    pub fn evict(&mut self, ranges: &[Range<usize>]) {
+        match self.eviction {
+            Eviction::Clflush => {
+                for range in ranges {
+                    clflush(range.clone(), self.line_size);
+                }
+            }
+            Eviction::Sweep => {
+                for i in (0..self.buffer.len()).step_by(self.line_size) {
+                    self.buffer[i] = self.buffer[i].wrapping_add(1);
+                }
+                black_box(&mut self.buffer);
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn clflush(range: Range<usize>, line_size: usize) {
+    use std::arch::x86_64::{_mm_clflush, _mm_mfence};
+
+    let start = range.start - range.start % line_size;
+    for line in (start..range.end).step_by(line_size) {
+        // SAFETY: clflush takes any address, SSE2 is part of x86_64
+        unsafe { _mm_clflush(line as *const u8) };
+    }
+    // SAFETY: SSE2 is part of x86_64
+    unsafe { _mm_mfence() };
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn clflush(_: Range<usize>, _: usize) {
+    unreachable!("Evictor::new falls back to a sweep")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_mode() {
+        for mode in [
+            CacheMode::Hot,
+            CacheMode::Warm,
+            CacheMode::Cold(Eviction::Clflush),
+            CacheMode::Cold(Eviction::Sweep),
+        ] {
+            assert_eq!(CacheMode::parse(&mode.to_string()), Some(mode));
+        }
+        assert_eq!(
+            CacheMode::parse("cold"),
+            Some(CacheMode::Cold(Eviction::default()))
+        );
+        assert_eq!(CacheMode::parse("cold:foo"), None);
+        assert_eq!(CacheMode::parse("warm:sweep"), None);
+    }
+
+    #[test]
+    fn test_footprint() {
+        let v = vec![0u32; 10];
+        let start = v.as_ptr() as usize;
+        let mut ranges = footprint(&v);
+        v[2..4].ranges(&mut ranges);
+        assert_eq!(ranges, [start..start + 40, start + 8..start + 16]);
+    }
+
+    #[test]
+    fn test_evict() {
+        let data = vec![1u64; 1000];
+        let topology = CacheTopology::fallback();
+        for eviction in [Eviction::Clflush, Eviction::Sweep] {
+            let mut evictor = Evictor::new(eviction, &topology);
+            evictor.evict(&footprint(&data));
+            assert!(evictor.eviction().is_supported());
+        }
+        assert!(data.iter().all(|&x| x == 1));
+    }
+}
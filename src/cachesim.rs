@@ -0,0 +1,519 @@
+//! A deterministic set-associative cache simulator, fed the address stream of
+//! a kernel to count its misses per level independently of the machine and
+//! its noise.
+//!
+//! Every level allocates on a miss, for reads and writes alike, and nothing
+//! is modelled beyond hits and misses: no write-backs, no prefetchers, no
+//! inclusion policy.
+
+use std::ops::Range;
+
+use crate::{
+    cache::Footprint,
+    harness::Rotation,
+    layout::{compute_accesses, make_layout, Layout},
+    topology::{CacheLevel, CacheTopology},
+    utils::{make_n_datasets, Cluster},
+};
+
+/// one load or store of a kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub addr: usize,
+    /// bytes
+    pub size: usize,
+    pub write: bool,
+}
+
+impl Access {
+    pub fn read(addr: usize, size: usize) -> Self {
+        Access {
+            addr,
+            size,
+            write: false,
+        }
+    }
+
+    pub fn write(addr: usize, size: usize) -> Self {
+        Access {
+            addr,
+            size,
+            write: true,
+        }
+    }
+
+    /// the lines of `line_size` bytes it touches
+    fn lines(&self, line_size: usize) -> Range<u64> {
+        let first = self.addr / line_size;
+        let last = (self.addr + self.size.max(1) - 1) / line_size;
+        first as u64..last as u64 + 1
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Replacement {
+    /// least recently used
+    Lru,
+    /// Bit-PLRU: one MRU bit per way, all but the newest cleared once every
+    /// bit is set, the first way with a clear bit evicted. Works for any
+    /// associativity.
+    Plru,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Geometry {
+    /// capacity in bytes
+    pub size: usize,
+    pub line_size: usize,
+    /// 0 for fully associative
+    pub ways: usize,
+}
+
+impl Geometry {
+    pub fn sets(&self) -> usize {
+        (self.size / self.line_size / self.ways()).max(1)
+    }
+
+    /// every line in one set when fully associative
+    pub fn ways(&self) -> usize {
+        match self.ways {
+            0 => (self.size / self.line_size).max(1),
+            ways => ways,
+        }
+    }
+}
+
+impl From<&CacheLevel> for Geometry {
+    fn from(c: &CacheLevel) -> Self {
+        Geometry {
+            size: c.size,
+            line_size: c.line_size,
+            ways: c.ways,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Stats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn miss_rate(&self) -> f64 {
+        self.misses as f64 / self.accesses().max(1) as f64
+    }
+}
+
+/// marks an empty way
+const INVALID: u64 = u64::MAX;
+
+/// one level: `sets * ways` tags and the replacement state of each way
+pub struct Cache {
+    geometry: Geometry,
+    replacement: Replacement,
+    tags: Vec<u64>,
+    /// last access for LRU, the MRU bit for PLRU
+    state: Vec<u64>,
+    tick: u64,
+    pub stats: Stats,
+}
+
+impl Cache {
+    pub fn new(geometry: Geometry, replacement: Replacement) -> Self {
+        let ways = geometry.sets() * geometry.ways();
+        Cache {
+            geometry,
+            replacement,
+            tags: vec![INVALID; ways],
+            state: vec![0; ways],
+            tick: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// Looks up `line`, a line number in this cache's line size, and
+    /// allocates it on a miss. True on a hit.
+    pub fn access(&mut self, line: u64) -> bool {
+        let sets = self.geometry.sets() as u64;
+        let (set, tag) = ((line % sets) as usize, line / sets);
+        let n = self.geometry.ways();
+        let ways = set * n..(set + 1) * n;
+        let hit = self.tags[ways.clone()].iter().position(|&t| t == tag);
+        let way = match hit {
+            Some(way) => way,
+            None => {
+                let way = self.victim(ways.clone());
+                self.tags[ways.start + way] = tag;
+                way
+            }
+        };
+        self.touch(ways, way);
+        if hit.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        hit.is_some()
+    }
+
+    /// an empty way if there is one, otherwise the one to evict
+    fn victim(&self, ways: Range<usize>) -> usize {
+        let state = &self.state[ways.clone()];
+        if let Some(way) = self.tags[ways].iter().position(|&t| t == INVALID) {
+            return way;
+        }
+        match self.replacement {
+            Replacement::Lru => (0..state.len()).min_by_key(|&w| state[w]).unwrap(),
+            Replacement::Plru => state.iter().position(|&mru| mru == 0).unwrap_or(0),
+        }
+    }
+
+    fn touch(&mut self, ways: Range<usize>, way: usize) {
+        let state = &mut self.state[ways];
+        match self.replacement {
+            Replacement::Lru => {
+                self.tick += 1;
+                state[way] = self.tick;
+            }
+            Replacement::Plru => {
+                state[way] = 1;
+                if state.iter().all(|&mru| mru == 1) {
+                    state.fill(0);
+                    state[way] = 1;
+                }
+            }
+        }
+    }
+
+    /// forgets all content, keeps the stats
+    pub fn flush(&mut self) {
+        self.tags.fill(INVALID);
+        self.state.fill(0);
+    }
+}
+
+/// Levels from L1 outwards. An access goes to the next level only for the
+/// lines it missed.
+pub struct Hierarchy {
+    pub levels: Vec<Cache>,
+}
+
+impl Hierarchy {
+    pub fn new(levels: &[Geometry], replacement: Replacement) -> Self {
+        Hierarchy {
+            levels: levels
+                .iter()
+                .map(|&geometry| Cache::new(geometry, replacement))
+                .collect(),
+        }
+    }
+
+    /// the data and unified caches of `topology`
+    pub fn from_topology(topology: &CacheTopology, replacement: Replacement) -> Self {
+        let levels: Vec<Geometry> = (1..)
+            .map_while(|level| topology.data_cache(level))
+            .map(Geometry::from)
+            .collect();
+        Self::new(&levels, replacement)
+    }
+
+    pub fn access(&mut self, access: Access) {
+        self.access_level(0, access);
+    }
+
+    fn access_level(&mut self, level: usize, access: Access) {
+        let Some(cache) = self.levels.get_mut(level) else {
+            return;
+        };
+        let line_size = cache.geometry.line_size;
+        for line in access.lines(line_size) {
+            if !self.levels[level].access(line) {
+                let addr = line as usize * line_size;
+                self.access_level(
+                    level + 1,
+                    Access {
+                        addr,
+                        size: line_size,
+                        ..access
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Vec<Stats> {
+        self.levels.iter().map(|c| c.stats).collect()
+    }
+
+    pub fn reset_stats(&mut self) {
+        for cache in &mut self.levels {
+            cache.stats = Stats::default();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for cache in &mut self.levels {
+            cache.flush();
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// Maps every allocation to its own page-aligned block of a made-up address
+/// space, so that simulated misses do not depend on where the allocator put
+/// the data.
+pub struct AddressMap {
+    /// real start, end and simulated start, sorted by real start
+    ranges: Vec<(usize, usize, usize)>,
+}
+
+/// alignment of every allocation in the simulated address space
+const PAGE: usize = 4096;
+
+impl AddressMap {
+    /// `ranges` in the order they are laid out
+    pub fn new(ranges: &[Range<usize>]) -> Self {
+        let mut next = PAGE;
+        let mut map: Vec<_> = ranges
+            .iter()
+            .map(|r| {
+                let start = next;
+                next += (r.len()).div_ceil(PAGE).max(1) * PAGE;
+                (r.start, r.end, start)
+            })
+            .collect();
+        map.sort_unstable();
+        AddressMap { ranges: map }
+    }
+
+    /// `access` in the simulated address space, unchanged outside of the
+    /// mapped allocations
+    pub fn map(&self, access: Access) -> Access {
+        let i = self
+            .ranges
+            .partition_point(|&(start, _, _)| start <= access.addr);
+        match i.checked_sub(1).map(|i| self.ranges[i]) {
+            Some((start, end, to)) if access.addr < end => Access {
+                addr: to + (access.addr - start),
+                ..access
+            },
+            _ => access,
+        }
+    }
+}
+
+/// misses of a simulated run, per level
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulation {
+    /// iterations counted
+    pub iterations: usize,
+    pub levels: Vec<Stats>,
+}
+
+impl Simulation {
+    pub fn misses_per_iteration(&self, level: usize) -> f64 {
+        self.levels[level].misses as f64 / self.iterations as f64
+    }
+}
+
+/// Simulates [`compute`](crate::layout::compute) over `fields` of `m`
+/// datasets of `n` elements of `L`, visited in `rotation` order as the
+/// registered layout benchmarks do. One period of the rotation warms the
+/// caches, the next one is counted.
+pub fn simulate_layout<L: Layout<F>, const F: usize, const K: usize>(
+    hierarchy: &mut Hierarchy,
+    fields: &[usize; K],
+    n: usize,
+    m: usize,
+    rotation: &Rotation,
+) -> Simulation {
+    let data_sets = make_n_datasets(m, |i| make_layout::<L, F>(i, n));
+    let result = vec![Cluster::splat(0.0); n];
+    let mut ranges = Vec::new();
+    for data_set in &data_sets {
+        data_set.ranges(&mut ranges);
+    }
+    result.ranges(&mut ranges);
+    let map = AddressMap::new(&ranges);
+
+    let indices = rotation.indices(m);
+    for counted in [false, true] {
+        hierarchy.reset_stats();
+        for &i in &indices {
+            compute_accesses(&data_sets[i], fields, &result, |a| {
+                hierarchy.access(map.map(a))
+            });
+        }
+        if counted {
+            break;
+        }
+    }
+    Simulation {
+        iterations: indices.len(),
+        levels: hierarchy.stats(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::footprint,
+        layout::{dense, AoS, Blob, Realigned, SoA, SPARSE_9},
+    };
+
+    fn one_set(ways: usize, replacement: Replacement) -> Cache {
+        let geometry = Geometry {
+            size: 64 * ways,
+            line_size: 64,
+            ways,
+        };
+        Cache::new(geometry, replacement)
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut cache = one_set(2, Replacement::Lru);
+        let hits: Vec<bool> = [1, 2, 1, 3, 2, 1].map(|l| cache.access(l)).into();
+        // 3 evicts 2, 2 evicts 1, 1 evicts 3
+        assert_eq!(hits, [false, false, true, false, false, false]);
+        assert_eq!(cache.stats, Stats { hits: 1, misses: 5 });
+    }
+
+    #[test]
+    fn test_plru() {
+        let mut cache = one_set(4, Replacement::Plru);
+        for line in [1, 2, 3, 4] {
+            assert!(!cache.access(line));
+        }
+        // all bits set by 4, cleared but for it: 1 is the first clear way
+        assert!(!cache.access(5));
+        assert!(cache.access(2) && cache.access(3) && cache.access(4));
+        // 3 set the last bit and cleared the one of 5
+        assert!(!cache.access(1));
+        assert!(cache.access(3) && cache.access(4));
+    }
+
+    #[test]
+    fn test_fully_associative() {
+        let geometry = Geometry {
+            size: 64 * 4,
+            line_size: 64,
+            ways: 0,
+        };
+        assert_eq!((geometry.sets(), geometry.ways()), (1, 4));
+        let mut cache = Cache::new(geometry, Replacement::Lru);
+        let hits: Vec<bool> = [1, 2, 3, 4, 1, 5, 2].map(|l| cache.access(l)).into();
+        assert_eq!(hits, [false, false, false, false, true, false, false]);
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let l1 = Geometry {
+            size: 64 * 2,
+            line_size: 64,
+            ways: 2,
+        };
+        let l2 = Geometry {
+            size: 64 * 8,
+            line_size: 64,
+            ways: 8,
+        };
+        let mut h = Hierarchy::new(&[l1, l2], Replacement::Lru);
+        // 96 bytes at 32 span lines 0 and 1
+        h.access(Access::read(32, 96));
+        for line in [2, 3, 0] {
+            h.access(Access::write(line * 64, 8));
+        }
+        assert_eq!(
+            h.stats(),
+            [Stats { hits: 0, misses: 5 }, Stats { hits: 1, misses: 4 }]
+        );
+    }
+
+    #[test]
+    fn test_address_map() {
+        let map = AddressMap::new(&[5000..5100, 1000..9000]);
+        assert_eq!(map.map(Access::read(5000, 4)).addr, PAGE);
+        assert_eq!(map.map(Access::read(1001, 4)).addr, 2 * PAGE + 1);
+        assert_eq!(map.map(Access::read(9000, 4)).addr, 9000);
+    }
+
+    /// misses of one cold call, in a cache big enough to miss once per line
+    fn compulsory<L: Layout<F>, const F: usize, const K: usize>(fields: &[usize; K]) -> u64 {
+        first_level::<L, F, K>(fields).misses
+    }
+
+    /// one pass of `compute` over 256 elements of `L` in a 1M L1
+    fn first_level<L: Layout<F>, const F: usize, const K: usize>(fields: &[usize; K]) -> Stats {
+        let l1 = Geometry {
+            size: 1 << 20,
+            line_size: 64,
+            ways: 16,
+        };
+        let mut h = Hierarchy::new(&[l1], Replacement::Lru);
+        let data_set = make_layout::<L, F>(0, 256);
+        let result = vec![Cluster::splat(0.0); 256];
+        let map = AddressMap::new(&[footprint(&data_set), footprint(&result)].concat());
+        compute_accesses(&data_set, fields, &result, |a| h.access(map.map(a)));
+        h.stats()[0]
+    }
+
+    #[test]
+    fn test_layouts() {
+        // 256 results of 32 bytes are 128 lines
+        let dense9 = 256 * 9 * 32 / 64 + 128;
+        assert_eq!(compulsory::<AoS<9>, 9, 9>(&dense()), dense9);
+        assert_eq!(compulsory::<SoA<9>, 9, 9>(&dense()), dense9);
+        // 6 of 9 columns, but every line of the 288 byte rows holds a
+        // touched field
+        assert_eq!(compulsory::<SoA<9>, 9, 6>(&SPARSE_9), 6 * 128 + 128);
+        let sparse_blob = compulsory::<Blob<9>, 9, 6>(&SPARSE_9);
+        assert!(sparse_blob > 6 * 128 + 128);
+        assert_eq!(sparse_blob, compulsory::<AoS<9>, 9, 6>(&SPARSE_9));
+    }
+
+    #[test]
+    fn test_realigned() {
+        type Line = Realigned<AoS<9>, 9, 64, 0>;
+        type Off4 = Realigned<AoS<9>, 9, 64, 4>;
+        let line = first_level::<Line, 9, 9>(&dense());
+        let off4 = first_level::<Off4, 9, 9>(&dense());
+        assert_eq!(line, first_level::<AoS<9>, 9, 9>(&dense()));
+        // every other Cluster straddles two lines
+        let loads = 256 * 9;
+        assert_eq!(off4.accesses(), line.accesses() + loads / 2);
+        assert_eq!(off4.misses, line.misses + 1);
+    }
+
+    #[test]
+    fn test_simulate_layout() {
+        let topology = CacheTopology::fallback();
+        let simulate = |replacement| {
+            let mut h = Hierarchy::from_topology(&topology, replacement);
+            simulate_layout::<AoS<7>, 7, 7>(&mut h, &dense(), 256, 300, &Rotation::default())
+        };
+        let lru = simulate(Replacement::Lru);
+        assert_eq!(lru, simulate(Replacement::Lru));
+        assert_eq!(lru.iterations, 300);
+        // the 56K of every dataset and its 8K of results overflow the 32K
+        // L1, the results stay in the 1M L2, all 300 datasets fit the 32M L3
+        assert_eq!(
+            lru.misses_per_iteration(0),
+            (256 * 7 * 32 / 64 + 128) as f64
+        );
+        assert_eq!(lru.misses_per_iteration(1), (256 * 7 * 32 / 64) as f64);
+        assert_eq!(lru.levels[2].misses, 0);
+        assert_eq!(simulate(Replacement::Plru).levels, lru.levels);
+    }
+}
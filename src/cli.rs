@@ -0,0 +1,695 @@
+//! The runner behind the `cache_bench` binary and `cargo bench`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{
+    aligned::Alignment,
+    baseline,
+    cache::CacheMode,
+    clock,
+    counters::Counters,
+    harness::{Config, Rotation},
+    latency,
+    registry::{self, Experiment, Filter, Size},
+    report::{self, Record},
+    roofline::{residence, Machine, Point, Weights},
+    stream::{self, Peak},
+    topology::CacheTopology,
+    trace::{self, Format},
+};
+
+pub const USAGE: &str = "\
+usage: cache_bench [OPTIONS] [NAME...]
+
+Runs the registered benchmarks whose name contains any NAME and that match
+every filter. Lists take comma-separated values.
+
+filters:
+  --layout LIST         aos, soa, blob, aosoa, strided
+  --fields LIST         field counts, e.g. 3,5,7
+  --sparse              only kernels touching a subset of the fields
+  --size LIST           small, big, l1, l2, l3, mem or elements per dataset
+  --datasets LIST       number of datasets rotated over
+  --module LIST         module of the kernel, e.g. layout, bench_runtime
+  --tag LIST            tags that all have to be present, e.g. big,sparse
+
+output:
+  --list                list the matching benchmarks instead of running them
+  --quick               short warmup and few samples
+  --counters            read event counters around every sample and print
+                        them per element (needs the perf feature, Linux)
+  --rotation POLICY     order the datasets are visited in: sequential,
+                        stride:K, random:SEED, same or custom:I,J,...
+                        [default: stride:7]
+  --cache LIST          cache state every iteration starts in: hot (same
+                        dataset), warm (rotation) or cold (evicted first,
+                        also cold:clflush, cold:sweep); several modes are
+                        run one after the other and tabled [default: warm]
+  --align LIST          allocations of the layout benchmarks: vec (wherever
+                        Vec puts them), line, page (aligned to a cache line
+                        or page) or off4, off16, off32 (that many bytes past
+                        a cache line); every one is registered under its own
+                        name [default: vec]
+  --stream              sweep the STREAM kernels over working-set sizes first
+                        and print every benchmark's bandwidth as a share of
+                        the peak of the level its data lives in
+  --latency             sweep a pointer chase over working-set sizes first and
+                        print the load-to-use latency per level
+  --roofline            measure the peak FLOP rate and bandwidths and place
+                        every benchmark on the roofline
+  --sqrt-flops N        FLOPs per lane of a sqrt for --roofline [default: 1]
+  --div-flops N         FLOPs per lane of a division for --roofline
+                        [default: 1]
+  --json PATH           write the records as JSON Lines
+  --csv PATH            write the records as CSV
+  --trace DIR           instead of timing, write the loads and stores of one
+                        rotation of every benchmark to DIR (needs the trace
+                        feature)
+  --trace-format FMT    din (Dinero), text or binary [default: din]
+  --save-baseline NAME  save the records as baseline NAME, kept in
+                        $CACHE_BENCH_BASELINES [default:
+                        target/cache_bench/baselines]
+  --baseline NAME       compare against baseline NAME, fail on regressions
+  --threshold PERCENT   tolerated slowdown for --baseline [default: 5]
+  -h, --help            print this help
+";
+
+/// known values of `--layout`
+pub const LAYOUTS: [&str; 5] = ["aos", "soa", "blob", "aosoa", "strided"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub filter: Filter,
+    pub list: bool,
+    pub quick: bool,
+    pub counters: bool,
+    pub rotation: Rotation,
+    /// every benchmark is run once per mode
+    pub caches: Vec<CacheMode>,
+    /// the layout benchmarks are registered once per alignment
+    pub alignments: Vec<Alignment>,
+    /// calibrate the bandwidths with a full STREAM sweep
+    pub stream: bool,
+    /// sweep the pointer-chasing latency first
+    pub latency: bool,
+    pub roofline: bool,
+    /// FLOPs of sqrt and division for the roofline
+    pub weights: Weights,
+    pub json: Option<PathBuf>,
+    pub csv: Option<PathBuf>,
+    /// directory to write traces to instead of measuring
+    pub trace: Option<PathBuf>,
+    pub trace_format: Format,
+    pub save_baseline: Option<String>,
+    pub baseline: Option<String>,
+    /// relative, `0.05` is 5%
+    pub threshold: f64,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            filter: Filter::default(),
+            list: false,
+            quick: false,
+            counters: false,
+            rotation: Rotation::default(),
+            caches: vec![CacheMode::default()],
+            alignments: vec![Alignment::default()],
+            stream: false,
+            latency: false,
+            roofline: false,
+            weights: Weights::default(),
+            json: None,
+            csv: None,
+            trace: None,
+            trace_format: Format::default(),
+            save_baseline: None,
+            baseline: None,
+            threshold: baseline::THRESHOLD,
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments after the program name. Options taking a value
+    /// accept it as the next argument or after `=`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline
+                    .map(str::to_string)
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{flag} needs a value"))
+            };
+            match flag.as_str() {
+                "--layout" => options.filter.layouts = list(&value()?, parse_layout)?,
+                "--fields" => options.filter.fields = list(&value()?, parse_number)?,
+                "--size" => {
+                    options.filter.sizes = list(&value()?, |s| {
+                        Size::parse(s).ok_or_else(|| format!("invalid size {s:?}"))
+                    })?
+                }
+                "--datasets" => options.filter.datasets = list(&value()?, parse_number)?,
+                "--module" => options.filter.modules = list(&value()?, |s| Ok(s.to_string()))?,
+                "--tag" => options.filter.tags = list(&value()?, |s| Ok(s.to_string()))?,
+                "--rotation" => {
+                    let value = value()?;
+                    options.rotation = Rotation::parse(&value)
+                        .ok_or_else(|| format!("invalid rotation {value:?}"))?;
+                }
+                "--cache" => {
+                    options.caches = list(&value()?, |s| {
+                        CacheMode::parse(s).ok_or_else(|| format!("invalid cache mode {s:?}"))
+                    })?
+                }
+                "--align" => {
+                    options.alignments = list(&value()?, |s| {
+                        Alignment::parse(s).ok_or_else(|| format!("invalid alignment {s:?}"))
+                    })?
+                }
+                "--sqrt-flops" => options.weights.sqrt = parse_flops(&value()?)?,
+                "--div-flops" => options.weights.div = parse_flops(&value()?)?,
+                "--json" => options.json = Some(value()?.into()),
+                "--csv" => options.csv = Some(value()?.into()),
+                "--trace" => options.trace = Some(value()?.into()),
+                "--trace-format" => {
+                    let value = value()?;
+                    options.trace_format = Format::parse(&value)
+                        .ok_or_else(|| format!("invalid trace format {value:?}"))?;
+                }
+                "--save-baseline" => options.save_baseline = Some(value()?),
+                "--baseline" => options.baseline = Some(value()?),
+                "--threshold" => {
+                    let value = value()?;
+                    let percent: f64 = value
+                        .parse()
+                        .ok()
+                        .filter(|p: &f64| *p >= 0.0)
+                        .ok_or_else(|| format!("invalid threshold {value:?}"))?;
+                    options.threshold = percent / 100.0;
+                }
+                _ if inline.is_some() => return Err(format!("{flag} takes no value")),
+                "--sparse" => options.filter.sparse = true,
+                "--list" => options.list = true,
+                "--quick" => options.quick = true,
+                "--counters" => options.counters = true,
+                "--stream" => options.stream = true,
+                "--latency" => options.latency = true,
+                "--roofline" => options.roofline = true,
+                "-h" | "--help" => options.help = true,
+                _ if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+                _ => options.filter.names.push(arg),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn list<T>(s: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<Vec<T>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(parse)
+        .collect()
+}
+
+fn parse_layout(s: &str) -> Result<String, String> {
+    if LAYOUTS.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "unknown layout {s:?}, expected one of {}",
+            LAYOUTS.join(", ")
+        ))
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("invalid number {s:?}"))
+}
+
+fn parse_flops(s: &str) -> Result<f64, String> {
+    s.parse()
+        .ok()
+        .filter(|f: &f64| *f >= 0.0)
+        .ok_or_else(|| format!("invalid FLOP count {s:?}"))
+}
+
+// ----------------------------------------------------------------------------
+
+/// Parses `args` (without the program name) and runs. Fails if the
+/// comparison against a baseline found regressions.
+pub fn main(args: impl IntoIterator<Item = String>) -> ExitCode {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if options.help {
+        print!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// false if the comparison against a baseline found regressions
+pub fn run(options: &Options) -> io::Result<bool> {
+    let topology = CacheTopology::detect_or_fallback();
+    let experiments: Vec<Experiment> =
+        registry::experiments_aligned(&topology, &options.alignments)
+            .into_iter()
+            .filter(|e| options.filter.matches(&e.meta))
+            .collect();
+    if options.list {
+        print_list(&experiments);
+        return Ok(true);
+    }
+    if experiments.is_empty() {
+        eprintln!("no benchmark matches");
+        return Ok(true);
+    }
+
+    if let Some(dir) = &options.trace {
+        write_traces(options, &experiments, dir)?;
+        return Ok(true);
+    }
+
+    if options.counters && Counters::open().is_empty() {
+        eprintln!("no event counters available, they need the perf feature on Linux");
+    }
+    let config = Config {
+        rotation: options.rotation.clone(),
+        counters: options.counters,
+        ..if options.quick {
+            Config::quick()
+        } else {
+            Config::default()
+        }
+    };
+    if options.latency {
+        let sizes = latency::sizes(&topology);
+        print_latency(&latency::sweep(&config, &sizes), &topology);
+    }
+    // the bandwidth peaks, for shares of them and the roofline
+    let peaks = if options.stream {
+        let sizes = stream::sizes(&topology);
+        let points = stream::sweep(&config, &sizes);
+        let peaks = stream::peaks(&points, &topology);
+        print_stream(&sizes, &points, &peaks, &topology);
+        peaks
+    } else if options.roofline {
+        stream::calibrate(&config, &topology)
+    } else {
+        Vec::new()
+    };
+    let mut records = Vec::with_capacity(experiments.len() * options.caches.len());
+    for experiment in experiments {
+        for &cache in &options.caches {
+            let record = experiment.measure(&Config {
+                cache,
+                ..config.clone()
+            });
+            println!(
+                "{:<42} n {:>5} m {:>5} {:<12}  {}{}",
+                record.meta.name,
+                record.meta.n,
+                record.meta.m,
+                record.meta.cache,
+                record.summary,
+                throughput(&record, &peaks, &topology)
+            );
+            if !record.counters.is_empty() {
+                println!("{:<42} {}", "", counters(&record));
+            }
+            report::emit(&record)?;
+            records.push(record);
+        }
+    }
+    if options.caches.len() > 1 {
+        print_caches(&records, &options.caches);
+    }
+    if options.roofline {
+        let machine = Machine::with_peaks(&config, &peaks);
+        print_roofline(&records, &machine, &topology, &options.weights);
+    }
+
+    if let Some(path) = &options.json {
+        let mut w = BufWriter::new(File::create(path)?);
+        report::write_jsonl(&mut w, &records)?;
+        w.flush()?;
+    }
+    if let Some(path) = &options.csv {
+        let mut w = BufWriter::new(File::create(path)?);
+        report::write_csv(&mut w, &records)?;
+        w.flush()?;
+    }
+    let dir = baseline::default_dir();
+    if let Some(name) = &options.save_baseline {
+        let path = baseline::save(&dir, name, &records)?;
+        eprintln!("saved baseline {name} to {}", path.display());
+    }
+    if let Some(name) = &options.baseline {
+        let mut base = baseline::load(&dir, name)?;
+        base.retain(|r| options.filter.matches(&r.meta));
+        let regressions = baseline::compare(&base, &records, options.threshold);
+        println!("\n{regressions}");
+        return Ok(regressions.passed());
+    }
+    Ok(true)
+}
+
+/// One trace per benchmark and cache mode, named after the benchmark, its
+/// element count and, if there are several, the mode.
+fn write_traces(options: &Options, experiments: &[Experiment], dir: &Path) -> io::Result<()> {
+    if !trace::enabled() {
+        return Err(io::Error::other(
+            "--trace needs the trace feature, build with --features trace",
+        ));
+    }
+    fs::create_dir_all(dir)?;
+    let config = Config {
+        rotation: options.rotation.clone(),
+        ..Config::default()
+    };
+    for experiment in experiments {
+        let meta = &experiment.meta;
+        for &cache in &options.caches {
+            let mut name = format!("{}_{}", meta.name, meta.n);
+            if options.caches.len() > 1 {
+                name += &format!("_{}", cache.to_string().replace(':', "_"));
+            }
+            let path = dir
+                .join(name)
+                .with_extension(options.trace_format.extension());
+            let accesses = (experiment.trace)(&Config {
+                cache,
+                ..config.clone()
+            });
+            let mut w = BufWriter::new(File::create(&path)?);
+            options.trace_format.write(&mut w, &accesses)?;
+            w.flush()?;
+            println!(
+                "{:<42} n {:>5} m {:>5} {:<12}  {:>9} accesses to {}",
+                meta.name,
+                meta.n,
+                meta.m,
+                cache.to_string(),
+                accesses.len(),
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `, x GB/s (p% of l2), y Melem/s, z cycles/elem`, as far as known
+fn throughput(record: &Record, peaks: &[Peak], topology: &CacheTopology) -> String {
+    let mut out = String::new();
+    if let Some(bytes) = record.bytes_per_s() {
+        out += &format!(", {:.2} GB/s", bytes / 1e9);
+        let target = residence(&record.meta, topology);
+        if let Some(peak) = stream::peak(peaks, target) {
+            out += &format!(" ({:.0}% of {target})", bytes / peak * 100.0);
+        }
+    }
+    if let Some(elements) = record.elements_per_s() {
+        out += &format!(", {:.1} Melem/s", elements / 1e6);
+    }
+    if let Some(cycles) = record.cycles_per_element() {
+        out += &format!(", {cycles:.2} cycles/elem");
+    }
+    out
+}
+
+/// every counter per element, or per iteration if there are no elements
+fn counters(record: &Record) -> String {
+    let counters: Vec<String> = record
+        .counters
+        .iter()
+        .map(|(c, n)| match record.per_element(c) {
+            Some(per_element) => format!("{c} {per_element:.3}/elem"),
+            None => format!("{c} {n:.1}"),
+        })
+        .collect();
+    counters.join(", ")
+}
+
+/// median in ns per cache mode, one line per benchmark
+fn print_caches(records: &[Record], caches: &[CacheMode]) {
+    print!("\n{:<42} {:>6} {:>5}", "benchmark", "n", "m");
+    for cache in caches {
+        print!(" {:>12}", cache.to_string());
+    }
+    println!();
+    for group in records.chunks(caches.len()) {
+        let meta = &group[0].meta;
+        print!("{:<42} {:>6} {:>5}", meta.name, meta.n, meta.m);
+        for record in group {
+            print!(" {:>12.1}", record.summary.median);
+        }
+        println!();
+    }
+}
+
+/// ns and cycles per load by working set, then the median of every level
+fn print_latency(points: &[latency::Point], topology: &CacheTopology) {
+    let cycles = |ns: f64| match clock::cycles_per_ns() {
+        Some(rate) => format!("{:>8.1}", ns * rate),
+        None => format!("{:>8}", "-"),
+    };
+    println!("{:>12} {:>8} {:>8}  level", "bytes", "ns/load", "cycles");
+    for p in points {
+        print!(
+            "{:>12} {:>8.2} {}",
+            p.bytes,
+            p.ns_per_load,
+            cycles(p.ns_per_load)
+        );
+        match stream::band(p.bytes, topology) {
+            Some(target) => println!("  {target}"),
+            None => println!(),
+        }
+    }
+    for (target, ns) in latency::ladder(points, topology) {
+        println!(
+            "latency {:<4} {ns:>8.2} ns {} cycles",
+            target.to_string(),
+            cycles(ns)
+        );
+    }
+    println!();
+}
+
+/// GB/s of every kernel by working set, then the peak of every level
+fn print_stream(
+    sizes: &[usize],
+    points: &[stream::Point],
+    peaks: &[Peak],
+    topology: &CacheTopology,
+) {
+    print!("{:>12}", "bytes");
+    for kernel in stream::Kernel::ALL {
+        print!(" {:>8}", kernel.name());
+    }
+    println!("  level");
+    for (i, &bytes) in sizes.iter().enumerate() {
+        print!("{bytes:>12}");
+        for kernel in 0..stream::Kernel::ALL.len() {
+            print!(" {:>8.2}", points[kernel * sizes.len() + i].bandwidth / 1e9);
+        }
+        match stream::band(bytes, topology) {
+            Some(target) => println!("  {target}"),
+            None => println!(),
+        }
+    }
+    for peak in peaks {
+        println!(
+            "peak {:<4} {:>8.2} GB/s ({})",
+            peak.target.to_string(),
+            peak.bandwidth / 1e9,
+            peak.kernel
+        );
+    }
+    println!();
+}
+
+/// the peaks, then every benchmark against the roof of the level its data
+/// lives in
+fn print_roofline(
+    records: &[Record],
+    machine: &Machine,
+    topology: &CacheTopology,
+    weights: &Weights,
+) {
+    print!("\npeak {:.1} GFLOP/s", machine.flops / 1e9);
+    for (target, bandwidth) in &machine.bandwidths {
+        print!(", {target} {:.1} GB/s", bandwidth / 1e9);
+    }
+    println!(
+        "\n\n{:<42} {:>6} {:<12} {:>9} {:>9} {:>8} {:>8} {:>5} {:>5}  bound",
+        "benchmark", "n", "cache", "flop/elem", "flop/byte", "GFLOP/s", "roof", "level", "eff"
+    );
+    for record in records {
+        let meta = &record.meta;
+        print!("{:<42} {:>6} {:<12} ", meta.name, meta.n, meta.cache);
+        match Point::place(record, machine, topology, weights) {
+            Some(point) => println!(
+                "{:>9.0} {:>9.3} {:>8.2} {:>8.2} {:>5} {:>4.0}%  {}",
+                meta.ops.flops(weights),
+                point.intensity,
+                point.flops / 1e9,
+                point.attainable / 1e9,
+                point.ceiling.to_string(),
+                point.efficiency() * 100.0,
+                point.bound
+            ),
+            None => println!("no FLOPs or bytes declared"),
+        }
+    }
+}
+
+fn print_list(experiments: &[Experiment]) {
+    println!(
+        "{:<42} {:<24} {:<6} {:>6} {:>6} {:>6}  {:<18} {:<8} tags",
+        "name", "module", "layout", "fields", "n", "m", "touched", "variant"
+    );
+    for e in experiments {
+        let meta = &e.meta;
+        let touched: Vec<String> = meta.touched.iter().map(ToString::to_string).collect();
+        println!(
+            "{:<42} {:<24} {:<6} {:>6} {:>6} {:>6}  {:<18} {:<8} {}",
+            meta.name,
+            meta.module,
+            meta.layout,
+            meta.fields,
+            meta.n,
+            meta.m,
+            touched.join(","),
+            meta.variant,
+            meta.tags.join(",")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache::Eviction, registry::N_BIG, topology::Target};
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn test_parse() {
+        let options =
+            parse("--layout aos,soa --fields=7,9 --sparse --size big,l2 --datasets 300 --module layout --tag big soa_")
+                .unwrap();
+        assert_eq!(
+            options.filter,
+            Filter {
+                layouts: vec!["aos".to_string(), "soa".to_string()],
+                fields: vec![7, 9],
+                sparse: true,
+                sizes: vec![Size::N(N_BIG), Size::Target(Target::Level(2))],
+                datasets: vec![300],
+                modules: vec!["layout".to_string()],
+                tags: vec!["big".to_string()],
+                names: vec!["soa_".to_string()],
+            }
+        );
+        assert!(!options.list);
+
+        let options =
+            parse("--list --quick --rotation random:3 --cache hot,cold:sweep --json a.jsonl --csv b.csv --baseline main --threshold 2.5")
+                .unwrap();
+        assert!(options.list && options.quick && !options.counters);
+        assert!(parse("--counters").unwrap().counters);
+        assert_eq!(options.rotation, Rotation::Random { seed: 3 });
+        assert_eq!(
+            options.caches,
+            [CacheMode::Hot, CacheMode::Cold(Eviction::Sweep)]
+        );
+        assert_eq!(options.json, Some(PathBuf::from("a.jsonl")));
+        assert_eq!(options.csv, Some(PathBuf::from("b.csv")));
+        assert_eq!(options.baseline.as_deref(), Some("main"));
+        assert_eq!(options.threshold, 0.025);
+        let options = parse("--trace out --trace-format text").unwrap();
+        assert_eq!(options.trace, Some(PathBuf::from("out")));
+        assert_eq!(options.trace_format, Format::Text);
+        assert_eq!(
+            parse("--align vec,off4,page").unwrap().alignments,
+            [Alignment::Vec, Alignment::Misaligned4, Alignment::Page]
+        );
+        let options = parse("--stream --latency --roofline --sqrt-flops 4 --div-flops=8").unwrap();
+        assert!(options.stream && options.latency && options.roofline);
+        assert_eq!(
+            options.weights,
+            Weights {
+                sqrt: 4.0,
+                div: 8.0
+            }
+        );
+        assert_eq!(parse("").unwrap(), Options::default());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("--layout aos,foo").unwrap_err(),
+            "unknown layout \"foo\", expected one of aos, soa, blob, aosoa, strided"
+        );
+        assert_eq!(parse("--fields x").unwrap_err(), "invalid number \"x\"");
+        assert_eq!(parse("--size huge").unwrap_err(), "invalid size \"huge\"");
+        assert_eq!(parse("--json").unwrap_err(), "--json needs a value");
+        assert_eq!(
+            parse("--rotation stride").unwrap_err(),
+            "invalid rotation \"stride\""
+        );
+        assert_eq!(
+            parse("--cache warm,tepid").unwrap_err(),
+            "invalid cache mode \"tepid\""
+        );
+        assert_eq!(
+            parse("--align line,off8").unwrap_err(),
+            "invalid alignment \"off8\""
+        );
+        assert_eq!(
+            parse("--trace-format pin").unwrap_err(),
+            "invalid trace format \"pin\""
+        );
+        assert_eq!(
+            parse("--div-flops -2").unwrap_err(),
+            "invalid FLOP count \"-2\""
+        );
+        assert_eq!(parse("--list=1").unwrap_err(), "--list takes no value");
+        assert_eq!(
+            parse("--threshold -1").unwrap_err(),
+            "invalid threshold \"-1\""
+        );
+        assert_eq!(parse("--bogus").unwrap_err(), "unknown option --bogus");
+    }
+}
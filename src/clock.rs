@@ -0,0 +1,59 @@
+//! The CPU's cycle counter, to express times in cycles.
+//!
+//! On x86_64 this is the time stamp counter, which ticks at a constant
+//! nominal rate rather than the actual core clock. Its rate is calibrated
+//! once against [`Instant`].
+
+use std::{
+    hint::black_box,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// how long [`cycles_per_ns`] calibrates for
+const CALIBRATION: Duration = Duration::from_millis(10);
+
+/// the current value of the cycle counter, if there is one
+#[inline(always)]
+pub fn counter() -> Option<u64> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // SAFETY: rdtsc is part of x86_64
+        Some(unsafe { std::arch::x86_64::_rdtsc() })
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        None
+    }
+}
+
+/// Rate of [`counter`] in ticks per ns, i.e. its frequency in GHz, measured
+/// on the first call.
+pub fn cycles_per_ns() -> Option<f64> {
+    static RATE: OnceLock<Option<f64>> = OnceLock::new();
+    *RATE.get_or_init(|| {
+        let (start, start_cycles) = (Instant::now(), counter()?);
+        while start.elapsed() < CALIBRATION {
+            black_box(());
+        }
+        let (ns, cycles) = (start.elapsed().as_nanos(), counter()?);
+        Some(cycles.wrapping_sub(start_cycles) as f64 / ns as f64).filter(|&r| r > 0.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles_per_ns() {
+        if counter().is_some() {
+            // anything between 100 MHz and 10 GHz
+            let rate = cycles_per_ns().unwrap();
+            assert!((0.1..10.0).contains(&rate), "{rate}");
+            assert_eq!(cycles_per_ns(), Some(rate));
+        } else {
+            assert_eq!(cycles_per_ns(), None);
+        }
+    }
+}
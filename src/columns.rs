@@ -0,0 +1,193 @@
+use crate::{
+    cache::Footprint,
+    layout::{dense, init_field},
+    registry::{Experiment, N_BIG, N_SMALL},
+    report::Meta,
+    roofline::Ops,
+    simd::StdFloat,
+    trace,
+    utils::{Cluster, M},
+};
+
+/// A sequence of `Row`s, stored row-wise (`Vec<Row>`) or column-wise (the
+/// container generated by `#[derive(SoA)]`).
+pub trait Rows: Footprint + Sized {
+    type Row;
+
+    fn with_capacity(n: usize) -> Self;
+
+    fn push(&mut self, row: Self::Row);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// copy of element `i`
+    fn row(&self, i: usize) -> Self::Row;
+
+    /// `n` rows `f(seed)..f(seed + n)`, like the `make_*` builders
+    fn make(seed: usize, n: usize, f: impl Fn(usize) -> Self::Row) -> Self {
+        let mut rows = Self::with_capacity(n);
+        for i in seed..seed + n {
+            rows.push(f(i));
+        }
+        rows
+    }
+}
+
+impl<T: Copy> Rows for Vec<T> {
+    type Row = T;
+
+    fn with_capacity(n: usize) -> Self {
+        Vec::with_capacity(n)
+    }
+
+    fn push(&mut self, row: T) {
+        Vec::push(self, row);
+    }
+
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    #[inline(always)]
+    fn row(&self, i: usize) -> T {
+        trace::read(&self[i])
+    }
+}
+
+/// A plain struct with a column-wise twin, implemented by `#[derive(SoA)]`.
+pub trait Record: Copy {
+    type SoA: Rows<Row = Self>;
+}
+
+pub fn make_aos<T: Record>(seed: usize, n: usize, f: impl Fn(usize) -> T) -> Vec<T> {
+    Rows::make(seed, n, f)
+}
+
+pub fn make_soa<T: Record>(seed: usize, n: usize, f: impl Fn(usize) -> T) -> T::SoA {
+    Rows::make(seed, n, f)
+}
+
+/// Applies `f` to every row, writes the result per element and returns the
+/// sum over all elements.
+#[inline(always)]
+pub fn compute_rows<R: Rows>(
+    data_set: &R,
+    result: &mut [Cluster],
+    f: impl Fn(R::Row) -> Cluster,
+) -> Cluster {
+    let mut sum = Cluster::splat(0.0);
+    for (i, r) in result.iter_mut().take(data_set.len()).enumerate() {
+        let tmp = f(data_set.row(i));
+        trace::write(r, tmp);
+        sum += tmp;
+    }
+    sum
+}
+
+// ----------------------------------------------------------------------------
+
+/// a plain struct as it would appear in user code, compared as `Vec<S7>`
+/// against its derived column-wise twin `S7SoA`
+#[derive(Clone, Copy, crate::SoA)]
+pub struct S7 {
+    pub a: Cluster,
+    pub b: Cluster,
+    pub c: Cluster,
+    pub d: Cluster,
+    pub e: Cluster,
+    pub f: Cluster,
+    pub g: Cluster,
+}
+
+pub fn make_s7(i: usize) -> S7 {
+    S7 {
+        a: init_field(i, 0, 7),
+        b: init_field(i, 1, 7),
+        c: init_field(i, 2, 7),
+        d: init_field(i, 3, 7),
+        e: init_field(i, 4, 7),
+        f: init_field(i, 5, 7),
+        g: init_field(i, 6, 7),
+    }
+}
+
+/// the dense 7 field kernel of [`compute`](crate::layout::compute)
+pub fn compute_s7(s7: S7) -> Cluster {
+    s7.a.mul_add(s7.b, s7.c)
+        .mul_add(s7.d, s7.e)
+        .mul_add(s7.f, s7.g)
+}
+
+/// `Vec<S7>` and `S7SoA` at both sizes
+pub fn experiments() -> Vec<Experiment> {
+    let meta = |layout: &str, n| {
+        Meta::kernel(
+            "columns",
+            &format!("7_{layout}"),
+            layout,
+            7,
+            &dense::<7>(),
+            n,
+            M,
+        )
+        .with_bytes(size_of::<S7>(), size_of::<Cluster>())
+        .with_ops(Ops::chain(7))
+        .with_variant("derive")
+        .with_tag("derive")
+        .with_tag(if n == N_SMALL { "small" } else { "big" })
+    };
+    let mut out = Vec::new();
+    for n in [N_SMALL, N_BIG] {
+        out.push(Experiment::rows::<Vec<S7>>(
+            meta("aos", n),
+            make_s7,
+            compute_s7,
+        ));
+        out.push(Experiment::rows::<<S7 as Record>::SoA>(
+            meta("soa", n),
+            make_s7,
+            compute_s7,
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SoA;
+
+    #[derive(Clone, Copy, Debug, PartialEq, SoA)]
+    struct Particle {
+        pos: Cluster,
+        /// doc comments and attributes on fields are skipped
+        #[allow(dead_code)]
+        pub(crate) mass: f32,
+        id: u32,
+        tags: Option<(u8, [u16; 2])>,
+    }
+
+    fn make_particle(i: usize) -> Particle {
+        Particle {
+            pos: Cluster::splat(i as f32),
+            mass: i as f32 * 0.5,
+            id: i as u32,
+            tags: i.is_multiple_of(2).then_some((i as u8, [1, 2])),
+        }
+    }
+
+    #[test]
+    fn test_derive_soa() {
+        let aos = make_aos(3, 10, make_particle);
+        let soa = make_soa(3, 10, make_particle);
+        assert_eq!(soa.len(), 10);
+        assert_eq!(soa.id, (3..13).collect::<Vec<u32>>());
+        for i in 0..10 {
+            assert_eq!(aos.row(i), soa.row(i));
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Hardware and software event counters around every sample, to see why one
+//! benchmark is slower than another and not only that it is.
+//!
+//! Backed by `perf_event_open` on Linux with the `perf` feature, and empty
+//! otherwise. Every counter the kernel refuses is skipped, so in containers
+//! and VMs without a PMU only the software ones remain, or none at all.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    Cycles,
+    Instructions,
+    BranchMisses,
+    /// L1 data cache read misses
+    L1dMisses,
+    /// last level cache read misses
+    LlcMisses,
+    /// data TLB read misses
+    DtlbMisses,
+    /// software
+    PageFaults,
+    /// software, ns the task was on a CPU
+    TaskClock,
+}
+
+impl Counter {
+    /// preferred order, hardware first
+    pub const ALL: [Counter; 8] = [
+        Counter::Cycles,
+        Counter::Instructions,
+        Counter::BranchMisses,
+        Counter::L1dMisses,
+        Counter::LlcMisses,
+        Counter::DtlbMisses,
+        Counter::PageFaults,
+        Counter::TaskClock,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::Cycles => "cycles",
+            Counter::Instructions => "instructions",
+            Counter::BranchMisses => "branch_misses",
+            Counter::L1dMisses => "l1d_misses",
+            Counter::LlcMisses => "llc_misses",
+            Counter::DtlbMisses => "dtlb_misses",
+            Counter::PageFaults => "page_faults",
+            Counter::TaskClock => "task_clock",
+        }
+    }
+}
+
+impl fmt::Display for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The counters that could be opened for this thread, counting user space
+/// only, which is all an unprivileged process may count.
+pub struct Counters {
+    counters: Vec<(Counter, sys::Event)>,
+}
+
+impl Counters {
+    /// every counter of [`Counter::ALL`] the kernel allows, possibly none
+    pub fn open() -> Self {
+        Counters {
+            counters: Counter::ALL
+                .into_iter()
+                .filter_map(|c| Some((c, sys::Event::open(c)?)))
+                .collect(),
+        }
+    }
+
+    /// counts nothing
+    pub fn none() -> Self {
+        Counters {
+            counters: Vec::new(),
+        }
+    }
+
+    pub fn available(&self) -> Vec<Counter> {
+        self.counters.iter().map(|&(c, _)| c).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// resets and starts every counter
+    pub fn start(&mut self) {
+        for (_, event) in &mut self.counters {
+            event.start();
+        }
+    }
+
+    /// Stops every counter and returns the counts since [`Counters::start`],
+    /// scaled up if the kernel multiplexed it. Counters that fail to read are
+    /// left out.
+    pub fn stop(&mut self) -> Vec<(Counter, f64)> {
+        for (_, event) in &mut self.counters {
+            event.stop();
+        }
+        self.counters
+            .iter_mut()
+            .filter_map(|(c, event)| Some((*c, event.read()?)))
+            .collect()
+    }
+}
+
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod sys {
+    //! The raw `perf_event_open` interface, declared by hand against the libc
+    //! `std` links anyway.
+
+    use std::{
+        fs::File,
+        io::Read,
+        os::fd::{AsRawFd, FromRawFd},
+    };
+
+    use super::Counter;
+
+    const TYPE_HARDWARE: u32 = 0;
+    const TYPE_SOFTWARE: u32 = 1;
+    const TYPE_HW_CACHE: u32 = 3;
+
+    const HW_CPU_CYCLES: u64 = 0;
+    const HW_INSTRUCTIONS: u64 = 1;
+    const HW_BRANCH_MISSES: u64 = 5;
+    const SW_TASK_CLOCK: u64 = 1;
+    const SW_PAGE_FAULTS: u64 = 2;
+
+    const CACHE_L1D: u64 = 0;
+    const CACHE_LL: u64 = 2;
+    const CACHE_DTLB: u64 = 3;
+    const CACHE_OP_READ: u64 = 0;
+    const CACHE_RESULT_MISS: u64 = 1;
+
+    const FORMAT_TOTAL_TIME_ENABLED: u64 = 1;
+    const FORMAT_TOTAL_TIME_RUNNING: u64 = 2;
+
+    const FLAG_DISABLED: u64 = 1;
+    const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+    const IOC_ENABLE: u64 = 0x2400;
+    const IOC_DISABLE: u64 = 0x2401;
+    const IOC_RESET: u64 = 0x2403;
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_PERF_EVENT_OPEN: i64 = 298;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_PERF_EVENT_OPEN: i64 = 241;
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const SYS_PERF_EVENT_OPEN: i64 = -1;
+
+    /// `struct perf_event_attr` up to `PERF_ATTR_SIZE_VER5`, the flag bits as
+    /// one word
+    #[repr(C)]
+    #[derive(Default)]
+    struct Attr {
+        kind: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+        config2: u64,
+        branch_sample_type: u64,
+        sample_regs_user: u64,
+        sample_stack_user: u32,
+        clockid: i32,
+        sample_regs_intr: u64,
+        aux_watermark: u32,
+        sample_max_stack: u16,
+        reserved: u16,
+    }
+
+    extern "C" {
+        fn syscall(number: i64, ...) -> i64;
+        fn ioctl(fd: i32, request: u64, ...) -> i32;
+    }
+
+    pub struct Event {
+        file: File,
+    }
+
+    impl Event {
+        /// this thread on any CPU, `None` if the kernel refuses
+        pub fn open(counter: Counter) -> Option<Self> {
+            if SYS_PERF_EVENT_OPEN < 0 {
+                return None;
+            }
+            let cache = |cache: u64| {
+                (
+                    TYPE_HW_CACHE,
+                    cache | CACHE_OP_READ << 8 | CACHE_RESULT_MISS << 16,
+                )
+            };
+            let (kind, config) = match counter {
+                Counter::Cycles => (TYPE_HARDWARE, HW_CPU_CYCLES),
+                Counter::Instructions => (TYPE_HARDWARE, HW_INSTRUCTIONS),
+                Counter::BranchMisses => (TYPE_HARDWARE, HW_BRANCH_MISSES),
+                Counter::L1dMisses => cache(CACHE_L1D),
+                Counter::LlcMisses => cache(CACHE_LL),
+                Counter::DtlbMisses => cache(CACHE_DTLB),
+                Counter::PageFaults => (TYPE_SOFTWARE, SW_PAGE_FAULTS),
+                Counter::TaskClock => (TYPE_SOFTWARE, SW_TASK_CLOCK),
+            };
+            let attr = Attr {
+                kind,
+                size: size_of::<Attr>() as u32,
+                config,
+                read_format: FORMAT_TOTAL_TIME_ENABLED | FORMAT_TOTAL_TIME_RUNNING,
+                flags: FLAG_DISABLED | FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+                ..Default::default()
+            };
+            // SAFETY: attr outlives the call, pid 0 and cpu -1 is this thread
+            // on any CPU, no group, no flags
+            let fd = unsafe {
+                syscall(
+                    SYS_PERF_EVENT_OPEN,
+                    &attr as *const Attr,
+                    0i64,
+                    -1i64,
+                    -1i64,
+                    0u64,
+                )
+            };
+            let fd = i32::try_from(fd).ok().filter(|&fd| fd >= 0)?;
+            // SAFETY: the fd was just opened and is owned by nobody else
+            Some(Event {
+                file: unsafe { File::from_raw_fd(fd) },
+            })
+        }
+
+        fn ioctl(&self, request: u64) {
+            // SAFETY: a perf event fd and a request without argument
+            unsafe { ioctl(self.file.as_raw_fd(), request, 0u64) };
+        }
+
+        pub fn start(&mut self) {
+            self.ioctl(IOC_RESET);
+            self.ioctl(IOC_ENABLE);
+        }
+
+        pub fn stop(&mut self) {
+            self.ioctl(IOC_DISABLE);
+        }
+
+        /// the count, scaled by enabled over running time, `None` if it never
+        /// ran
+        pub fn read(&mut self) -> Option<f64> {
+            let mut buf = [0u8; 24];
+            self.file.read_exact(&mut buf).ok()?;
+            let word = |i: usize| u64::from_ne_bytes(buf[i * 8..i * 8 + 8].try_into().unwrap());
+            let (value, enabled, running) = (word(0), word(1), word(2));
+            (running > 0).then(|| value as f64 * enabled as f64 / running as f64)
+        }
+    }
+}
+
+#[cfg(not(all(feature = "perf", target_os = "linux")))]
+mod sys {
+    use super::Counter;
+
+    /// never opened without the `perf` feature on Linux
+    pub enum Event {}
+
+    impl Event {
+        pub fn open(_: Counter) -> Option<Self> {
+            None
+        }
+
+        pub fn start(&mut self) {
+            match *self {}
+        }
+
+        pub fn stop(&mut self) {
+            match *self {}
+        }
+
+        pub fn read(&mut self) -> Option<f64> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let mut counters = Counters::open();
+        let available = counters.available();
+        if cfg!(not(all(feature = "perf", target_os = "linux"))) {
+            assert!(available.is_empty());
+        }
+        counters.start();
+        let v = std::hint::black_box(vec![1u8; 1 << 20]);
+        let counts = counters.stop();
+        drop(v);
+        for (counter, count) in counts {
+            assert!(available.contains(&counter));
+            assert!(count >= 0.0, "{counter} {count}");
+        }
+        assert!(Counters::none().stop().is_empty());
+    }
+}
@@ -0,0 +1,529 @@
+//! Timing loop used by every benchmark: warmup, iteration calibration and
+//! sampling. Plain `std`, so it runs on stable and from a normal binary.
+
+use std::{
+    fmt,
+    hint::black_box,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cache::{CacheMode, Evictor, Footprint},
+    counters::{Counter, Counters},
+    rng::Rng,
+    stats::{self, Summary},
+    topology::CacheTopology,
+    trace::{capture, Access},
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// time spent running the benchmark before sampling, also used to
+    /// calibrate the iterations per sample
+    pub warmup: Duration,
+    /// target duration of one sample
+    pub sample_time: Duration,
+    /// number of samples
+    pub samples: usize,
+    /// order in which iterations visit the datasets
+    pub rotation: Rotation,
+    /// cache state every iteration starts in
+    pub cache: CacheMode,
+    /// read the event [`Counters`] around every sample
+    pub counters: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            warmup: Duration::from_millis(100),
+            sample_time: Duration::from_millis(5),
+            samples: 50,
+            rotation: Rotation::default(),
+            cache: CacheMode::default(),
+            counters: false,
+        }
+    }
+}
+
+impl Config {
+    /// a short run for tests and smoke checks
+    pub fn quick() -> Self {
+        Config {
+            warmup: Duration::from_millis(1),
+            sample_time: Duration::from_micros(100),
+            samples: 5,
+            ..Default::default()
+        }
+    }
+
+    /// the rotation actually used, [`Rotation::Same`] when hot
+    pub fn effective_rotation(&self) -> Rotation {
+        match self.cache {
+            CacheMode::Hot => Rotation::Same,
+            CacheMode::Warm | CacheMode::Cold(_) => self.rotation.clone(),
+        }
+    }
+}
+
+/// Order in which successive iterations visit the datasets. The sequence of
+/// dataset indices is repeated once exhausted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// `0, 1, 2, ...`
+    Sequential,
+    /// `0, k, 2k, ...` modulo the number of datasets. Visits every dataset if
+    /// `k` and the number of datasets are coprime.
+    Stride(usize),
+    /// a permutation of all datasets, shuffled with `seed`
+    Random { seed: u64 },
+    /// always dataset 0, which stays in cache
+    Same,
+    /// these indices, modulo the number of datasets
+    Custom(Vec<usize>),
+}
+
+/// stride 7, what the benchmarks always used
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation::Stride(7)
+    }
+}
+
+impl Rotation {
+    /// one period of the index sequence over `len` datasets
+    pub fn indices(&self, len: usize) -> Vec<usize> {
+        assert!(len > 0, "no datasets to rotate over");
+        match self {
+            Rotation::Sequential => (0..len).collect(),
+            Rotation::Stride(k) => {
+                let mut indices = vec![0];
+                let mut i = k % len;
+                while i != 0 {
+                    indices.push(i);
+                    i = (i + k) % len;
+                }
+                indices
+            }
+            Rotation::Random { seed } => {
+                let mut indices: Vec<usize> = (0..len).collect();
+                Rng::new(*seed).shuffle(&mut indices);
+                indices
+            }
+            Rotation::Same => vec![0],
+            Rotation::Custom(indices) if indices.is_empty() => vec![0],
+            Rotation::Custom(indices) => indices.iter().map(|i| i % len).collect(),
+        }
+    }
+
+    /// `sequential`, `stride:K`, `random:SEED`, `same` or `custom:I,J,...`,
+    /// as written by `Display`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+        match (kind, arg) {
+            ("sequential", "") => Some(Rotation::Sequential),
+            ("stride", k) => k.parse().ok().filter(|&k| k > 0).map(Rotation::Stride),
+            ("random", seed) => seed.parse().ok().map(|seed| Rotation::Random { seed }),
+            ("same", "") => Some(Rotation::Same),
+            ("custom", indices) => indices
+                .split(',')
+                .map(|i| i.trim().parse().ok())
+                .collect::<Option<_>>()
+                .map(Rotation::Custom),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rotation::Sequential => f.write_str("sequential"),
+            Rotation::Stride(k) => write!(f, "stride:{k}"),
+            Rotation::Random { seed } => write!(f, "random:{seed}"),
+            Rotation::Same => f.write_str("same"),
+            Rotation::Custom(indices) => {
+                let indices: Vec<String> = indices.iter().map(ToString::to_string).collect();
+                write!(f, "custom:{}", indices.join(","))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// iterations per sample
+    pub iters: u64,
+    /// time per iteration of every sample in ns
+    pub samples: Vec<f64>,
+    /// median count per iteration of every counter available, if enabled
+    pub counters: Vec<(Counter, f64)>,
+}
+
+impl Measurement {
+    pub fn median(&self) -> f64 {
+        stats::median(&self.samples)
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::new(&self.samples)
+    }
+}
+
+/// counts per iteration of every sample, by counter
+#[derive(Default)]
+struct Counts(Vec<(Counter, Vec<f64>)>);
+
+impl Counts {
+    fn push(&mut self, counts: Vec<(Counter, f64)>, iters: u64) {
+        for (counter, count) in counts {
+            let per_iter = count / iters as f64;
+            match self.0.iter_mut().find(|(c, _)| *c == counter) {
+                Some((_, samples)) => samples.push(per_iter),
+                None => self.0.push((counter, vec![per_iter])),
+            }
+        }
+    }
+
+    fn medians(&self) -> Vec<(Counter, f64)> {
+        self.0
+            .iter()
+            .map(|(c, samples)| (*c, stats::median(samples)))
+            .collect()
+    }
+}
+
+/// the counters to read around every sample of `config`
+fn counters(config: &Config) -> Counters {
+    if config.counters {
+        Counters::open()
+    } else {
+        Counters::none()
+    }
+}
+
+#[inline(never)]
+fn time<R>(iters: u64, f: &mut impl FnMut() -> R) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iters {
+        black_box(f());
+    }
+    start.elapsed()
+}
+
+/// Runs `f` for `config.warmup`, doubling the batch size until a batch takes
+/// at least `config.sample_time`, then takes `config.samples` samples of the
+/// calibrated number of iterations.
+pub fn run<R>(config: &Config, mut f: impl FnMut() -> R) -> Measurement {
+    let start = Instant::now();
+    let mut iters = 1u64;
+    let mut elapsed = time(iters, &mut f);
+    while start.elapsed() < config.warmup || elapsed < config.sample_time {
+        if elapsed < config.sample_time {
+            iters *= 2;
+        }
+        elapsed = time(iters, &mut f);
+    }
+    let ns_per_iter = elapsed.as_nanos() as f64 / iters as f64;
+    let iters = ((config.sample_time.as_nanos() as f64 / ns_per_iter) as u64).max(1);
+
+    let mut counters = counters(config);
+    let mut counts = Counts::default();
+    let samples = (0..config.samples)
+        .map(|_| {
+            counters.start();
+            let elapsed = time(iters, &mut f);
+            counts.push(counters.stop(), iters);
+            elapsed.as_nanos() as f64 / iters as f64
+        })
+        .collect();
+    Measurement {
+        iters,
+        samples,
+        counters: counts.medians(),
+    }
+}
+
+/// Returns a closure calling `f` with the next dataset of `rotation` on
+/// every call.
+pub fn rotate<'a, T, R>(
+    data_sets: &'a [T],
+    rotation: &Rotation,
+    mut f: impl FnMut(&T) -> R + 'a,
+) -> impl FnMut() -> R + 'a {
+    let indices = rotation.indices(data_sets.len());
+    let mut i = 0;
+    move || {
+        let r = f(&data_sets[indices[i]]);
+        i += 1;
+        if i == indices.len() {
+            i = 0;
+        }
+        r
+    }
+}
+
+/// Like [`rotate`] over pairs of input and output datasets of equal count.
+pub fn rotate_pair<'a, T, U, R>(
+    data_sets_in: &'a [T],
+    data_sets_out: &'a mut [U],
+    rotation: &Rotation,
+    mut f: impl FnMut(&T, &mut U) -> R + 'a,
+) -> impl FnMut() -> R + 'a {
+    assert_eq!(data_sets_in.len(), data_sets_out.len());
+    let indices = rotation.indices(data_sets_in.len());
+    let mut i = 0;
+    move || {
+        let j = indices[i];
+        let r = f(&data_sets_in[j], &mut data_sets_out[j]);
+        i += 1;
+        if i == indices.len() {
+            i = 0;
+        }
+        r
+    }
+}
+
+/// Measures `f` over `data_sets` in the cache state of `config.cache`.
+/// `extra` is any other memory `f` touches, e.g. its output, flushed
+/// alongside the dataset when cold.
+pub fn run_on<T: Footprint, R>(
+    config: &Config,
+    data_sets: &[T],
+    extra: &[Range<usize>],
+    mut f: impl FnMut(&T) -> R,
+) -> Measurement {
+    match config.cache {
+        CacheMode::Hot | CacheMode::Warm => {
+            run(config, rotate(data_sets, &config.effective_rotation(), f))
+        }
+        CacheMode::Cold(_) => {
+            let ranges = data_sets
+                .iter()
+                .map(|data_set| {
+                    let mut ranges = extra.to_vec();
+                    data_set.ranges(&mut ranges);
+                    ranges
+                })
+                .collect::<Vec<_>>();
+            run_cold(config, &ranges, |i| f(&data_sets[i]))
+        }
+    }
+}
+
+/// Like [`run_on`] over pairs of input and output datasets of equal count.
+pub fn run_on_pair<T: Footprint, U: Footprint, R>(
+    config: &Config,
+    data_sets_in: &[T],
+    data_sets_out: &mut [U],
+    mut f: impl FnMut(&T, &mut U) -> R,
+) -> Measurement {
+    match config.cache {
+        CacheMode::Hot | CacheMode::Warm => run(
+            config,
+            rotate_pair(data_sets_in, data_sets_out, &config.effective_rotation(), f),
+        ),
+        CacheMode::Cold(_) => {
+            assert_eq!(data_sets_in.len(), data_sets_out.len());
+            let ranges = data_sets_in
+                .iter()
+                .zip(data_sets_out.iter())
+                .map(|(d_in, d_out)| {
+                    let mut ranges = Vec::new();
+                    d_in.ranges(&mut ranges);
+                    d_out.ranges(&mut ranges);
+                    ranges
+                })
+                .collect::<Vec<_>>();
+            run_cold(config, &ranges, |i| {
+                f(&data_sets_in[i], &mut data_sets_out[i])
+            })
+        }
+    }
+}
+
+/// The accesses of `f` over one period of the rotation of `config` over
+/// `data_sets`, untimed. Empty without the `trace` feature.
+pub fn trace_on<T, R>(config: &Config, data_sets: &[T], mut f: impl FnMut(&T) -> R) -> Vec<Access> {
+    let indices = config.effective_rotation().indices(data_sets.len());
+    let ((), trace) = capture(|| {
+        for i in indices {
+            black_box(f(&data_sets[i]));
+        }
+    });
+    trace
+}
+
+/// Like [`trace_on`] over pairs of input and output datasets of equal count.
+pub fn trace_on_pair<T, U, R>(
+    config: &Config,
+    data_sets_in: &[T],
+    data_sets_out: &mut [U],
+    mut f: impl FnMut(&T, &mut U) -> R,
+) -> Vec<Access> {
+    assert_eq!(data_sets_in.len(), data_sets_out.len());
+    let indices = config.effective_rotation().indices(data_sets_in.len());
+    let ((), trace) = capture(|| {
+        for i in indices {
+            black_box(f(&data_sets_in[i], &mut data_sets_out[i]));
+        }
+    });
+    trace
+}
+
+/// One iteration per sample, each on the next dataset of the rotation and
+/// preceded by an untimed eviction of `ranges[dataset]`. There is no
+/// calibration, a single untimed call stands in for the warmup.
+fn run_cold<R>(
+    config: &Config,
+    ranges: &[Vec<Range<usize>>],
+    mut f: impl FnMut(usize) -> R,
+) -> Measurement {
+    let CacheMode::Cold(eviction) = config.cache else {
+        unreachable!("run_cold without CacheMode::Cold")
+    };
+    let mut evictor = Evictor::new(eviction, &CacheTopology::detect_or_fallback());
+    let mut counters = counters(config);
+    let mut counts = Counts::default();
+    let indices = config.rotation.indices(ranges.len());
+    black_box(f(indices[0]));
+    let samples = (0..config.samples)
+        .map(|s| {
+            let i = indices[s % indices.len()];
+            evictor.evict(&ranges[i]);
+            counters.start();
+            let elapsed = time(1, &mut || f(i));
+            counts.push(counters.stop(), 1);
+            elapsed.as_nanos() as f64
+        })
+        .collect();
+    Measurement {
+        iters: 1,
+        samples,
+        counters: counts.medians(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Eviction;
+
+    #[test]
+    fn test_run() {
+        let mut calls = 0u64;
+        let config = Config::quick();
+        let m = run(&config, || {
+            calls += 1;
+            black_box(calls)
+        });
+        assert_eq!(m.samples.len(), config.samples);
+        assert!(calls >= m.iters * config.samples as u64);
+        assert!(m.samples.iter().all(|&s| s > 0.0));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let data_sets = [0, 1, 2, 3, 4];
+        let mut next = rotate(&data_sets, &Rotation::Stride(3), |&d| d);
+        let seen: Vec<_> = (0..6).map(|_| next()).collect();
+        assert_eq!(seen, vec![0, 3, 1, 4, 2, 0]);
+
+        // every one of 300 datasets is visited, not only the first 256
+        let data_sets_in: Vec<usize> = (0..300).collect();
+        let mut data_sets_out = vec![0; 300];
+        let mut next = rotate_pair(
+            &data_sets_in,
+            &mut data_sets_out,
+            &Rotation::default(),
+            |_, out| *out += 1,
+        );
+        for _ in 0..600 {
+            next();
+        }
+        drop(next);
+        assert!(data_sets_out.iter().all(|&n| n == 2));
+    }
+
+    #[test]
+    fn test_run_on() {
+        let data_sets: Vec<Vec<usize>> = (0..4).map(|i| vec![i; 16]).collect();
+        let visits = |cache| {
+            let config = Config {
+                cache,
+                ..Config::quick()
+            };
+            let mut seen = [0; 4];
+            let m = run_on(&config, &data_sets, &[], |d| seen[d[0]] += 1);
+            (m, seen)
+        };
+        let (_, seen) = visits(CacheMode::Hot);
+        assert!(seen[0] > 0 && seen[1..] == [0, 0, 0]);
+        let (_, seen) = visits(CacheMode::Warm);
+        assert!(seen.iter().all(|&n| n > 0));
+        // stride 7 over 4 datasets visits 0, 3, 2, 1, after one warmup call
+        let (m, seen) = visits(CacheMode::Cold(Eviction::default()));
+        assert_eq!(m.iters, 1);
+        assert_eq!(seen, [3, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_trace_on() {
+        let data_sets: Vec<Vec<u64>> = (0..4).map(|i| vec![i; 2]).collect();
+        let mut seen = Vec::new();
+        let trace = trace_on(&Config::quick(), &data_sets, |d| {
+            seen.push(d[0]);
+            crate::trace::read(&d[1])
+        });
+        assert_eq!(seen, [0, 3, 2, 1]);
+        if crate::trace::enabled() {
+            let addrs: Vec<usize> = trace.iter().map(|a| a.addr).collect();
+            let expected: Vec<usize> = seen
+                .iter()
+                .map(|&i| &data_sets[i as usize][1] as *const u64 as usize)
+                .collect();
+            assert_eq!(addrs, expected);
+        } else {
+            assert!(trace.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_rotation() {
+        assert_eq!(Rotation::Sequential.indices(3), [0, 1, 2]);
+        assert_eq!(Rotation::Stride(2).indices(5), [0, 2, 4, 1, 3]);
+        assert_eq!(Rotation::Stride(2).indices(4), [0, 2]);
+        assert_eq!(Rotation::Same.indices(4), [0]);
+        assert_eq!(Rotation::Custom(vec![3, 1, 7]).indices(5), [3, 1, 2]);
+        let mut random = Rotation::Random { seed: 1 }.indices(100);
+        assert_eq!(random, Rotation::Random { seed: 1 }.indices(100));
+        assert_ne!(random, Rotation::Sequential.indices(100));
+        random.sort();
+        assert_eq!(random, Rotation::Sequential.indices(100));
+
+        for rotation in [
+            Rotation::Sequential,
+            Rotation::Stride(7),
+            Rotation::Random { seed: 42 },
+            Rotation::Same,
+            Rotation::Custom(vec![0, 5, 3]),
+        ] {
+            assert_eq!(Rotation::parse(&rotation.to_string()), Some(rotation));
+        }
+        assert_eq!(Rotation::parse("stride:0"), None);
+        assert_eq!(Rotation::parse("custom:1,x"), None);
+        assert_eq!(Rotation::parse("same:1"), None);
+    }
+
+    #[test]
+    fn test_median() {
+        let m = Measurement {
+            iters: 1,
+            samples: vec![4.0, 1.0, 3.0, 2.0],
+            counters: Vec::new(),
+        };
+        assert_eq!(m.median(), 2.5);
+        assert_eq!(m.summary().mean, 2.5);
+    }
+}
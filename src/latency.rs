@@ -0,0 +1,233 @@
+//! Load-to-use latency per cache level, lmbench style: chasing pointers
+//! through a randomly permuted cyclic chain of `Cluster`-sized nodes, where
+//! every load depends on the one before.
+//!
+//! The chain is a single cycle through all nodes (Sattolo), so a chase never
+//! gets stuck in a short loop that fits a smaller cache. Random order also
+//! defeats the prefetchers and, for large working sets, the TLB, whose
+//! misses are part of the latency measured.
+
+use std::{ops::Range, slice};
+
+use crate::{
+    cache::Footprint,
+    harness::{self, Config},
+    registry::Experiment,
+    report::Meta,
+    rng::Rng,
+    stats,
+    stream::band,
+    sweep::geometric,
+    topology::{CacheTopology, Target},
+    trace,
+};
+
+/// dependent loads per iteration of the experiments
+pub const STEPS: usize = 1024;
+
+/// one link of the chain, as large as a `Cluster`
+#[repr(C, align(32))]
+struct Node {
+    next: *const Node,
+}
+
+/// a cyclic chain through all its nodes in random order
+pub struct Chain {
+    nodes: Vec<Node>,
+}
+
+impl Chain {
+    /// `n` nodes, at least one, linked in an order shuffled with `seed`
+    pub fn new(n: usize, seed: u64) -> Self {
+        let n = n.max(1);
+        let mut order: Vec<usize> = (0..n).collect();
+        Rng::new(seed).sattolo(&mut order);
+        let mut nodes: Vec<Node> = (0..n)
+            .map(|_| Node {
+                next: std::ptr::null(),
+            })
+            .collect();
+        let base = nodes.as_mut_ptr();
+        for (i, &next) in order.iter().enumerate() {
+            // SAFETY: both indices are below n, the length of nodes
+            unsafe { (*base.add(i)).next = base.add(next) };
+        }
+        Chain { nodes }
+    }
+
+    /// the chain for about `bytes` of nodes
+    pub fn with_bytes(bytes: usize, seed: u64) -> Self {
+        Self::new(bytes / size_of::<Node>(), seed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Follows `steps` links from node `from` and returns where it ended.
+    pub fn chase(&self, from: usize, steps: usize) -> usize {
+        let mut p: *const Node = &self.nodes[from];
+        for _ in 0..steps {
+            // SAFETY: every link points into nodes, which is never resized
+            p = trace::read(unsafe { &(*p).next });
+        }
+        // SAFETY: p points into nodes
+        unsafe { p.offset_from(self.nodes.as_ptr()) as usize }
+    }
+}
+
+impl Footprint for Chain {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        self.nodes.ranges(out);
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// one working-set size of the ladder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    /// bytes of the chain
+    pub bytes: usize,
+    /// median ns per dependent load
+    pub ns_per_load: f64,
+}
+
+/// Times [`STEPS`] loads per iteration on a chain of about `bytes`, carrying
+/// on from where the last iteration ended.
+pub fn measure(config: &Config, bytes: usize) -> Point {
+    let chain = Chain::with_bytes(bytes, 0);
+    let mut at = 0;
+    let m = harness::run(config, || {
+        at = chain.chase(at, STEPS);
+        at
+    });
+    Point {
+        bytes: chain.len() * size_of::<Node>(),
+        ns_per_load: m.median() / STEPS as f64,
+    }
+}
+
+/// working sets of [`sweep`]: two per doubling from 4K to the memory
+/// working set of `topology`
+pub fn sizes(topology: &CacheTopology) -> Vec<usize> {
+    geometric(4 << 10, topology.working_set(Target::Memory), 2)
+}
+
+pub fn sweep(config: &Config, sizes: &[usize]) -> Vec<Point> {
+    sizes.iter().map(|&bytes| measure(config, bytes)).collect()
+}
+
+/// The median latency of the points in the [`band`] of every level and of
+/// memory, in that order. Levels without points are left out.
+pub fn ladder(points: &[Point], topology: &CacheTopology) -> Vec<(Target, f64)> {
+    let levels = (1..).map_while(|level| topology.data_cache(level));
+    levels
+        .map(|cache| Target::Level(cache.level))
+        .chain([Target::Memory])
+        .filter_map(|target| {
+            let ns: Vec<f64> = points
+                .iter()
+                .filter(|p| band(p.bytes, topology) == Some(target))
+                .map(|p| p.ns_per_load)
+                .collect();
+            (!ns.is_empty()).then(|| (target, stats::median(&ns)))
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------------------
+
+/// One chase per level of `topology` and one in memory, each over the
+/// [`working set`](CacheTopology::working_set) of its target. An element is
+/// one dependent load.
+pub fn experiments(topology: &CacheTopology) -> Vec<Experiment> {
+    let levels = (1..).map_while(|level| topology.data_cache(level));
+    levels
+        .map(|cache| Target::Level(cache.level))
+        .chain([Target::Memory])
+        .map(|target| {
+            let n = topology.working_set(target) / size_of::<Node>();
+            let meta = Meta {
+                elements: STEPS,
+                ..Meta::kernel("latency", "chase", "chain", 1, &[0], n, 1)
+            }
+            .with_bytes(0, 0)
+            .with_variant(&target.to_string())
+            .with_tag("latency")
+            .with_tag(&target.to_string());
+            Experiment {
+                meta,
+                run: Box::new(move |config| {
+                    let chain = Chain::new(n, 0);
+                    let mut at = 0;
+                    harness::run_on(config, slice::from_ref(&chain), &[], |chain| {
+                        at = chain.chase(at, STEPS);
+                        at
+                    })
+                }),
+                trace: Box::new(move |config| {
+                    let chain = Chain::new(n, 0);
+                    harness::trace_on(config, slice::from_ref(&chain), |chain| {
+                        chain.chase(0, STEPS)
+                    })
+                }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Cluster;
+
+    #[test]
+    fn test_chain() {
+        assert_eq!(size_of::<Node>(), size_of::<Cluster>());
+        let chain = Chain::new(1000, 7);
+        assert_eq!(chain.len(), 1000);
+        // a single cycle: back at the start after exactly n steps
+        let mut at = 0;
+        for steps in 1..1000 {
+            at = chain.chase(at, 1);
+            assert_ne!(at, 0, "cycle of {steps}");
+        }
+        assert_eq!(chain.chase(at, 1), 0);
+        assert_eq!(chain.chase(0, 1000), 0);
+        assert_eq!(Chain::new(0, 0).chase(0, 5), 0);
+
+        let (end, trace) = trace::capture(|| chain.chase(0, 3));
+        assert_eq!(end, chain.chase(0, 3));
+        assert_eq!(trace.len(), if trace::enabled() { 3 } else { 0 });
+    }
+
+    #[test]
+    fn test_ladder() {
+        let topology = CacheTopology::fallback();
+        let point = |bytes, ns_per_load| Point { bytes, ns_per_load };
+        let points = [
+            point(8 << 10, 1.0),
+            point(16 << 10, 1.5),
+            point(64 << 10, 4.0),
+            point(256 << 10, 5.0),
+            point(512 << 10, 3.0),
+            point(256 << 20, 90.0),
+        ];
+        assert_eq!(
+            ladder(&points, &topology),
+            [
+                (Target::Level(1), 1.25),
+                (Target::Level(2), 4.0),
+                (Target::Memory, 90.0)
+            ]
+        );
+        let measured = measure(&Config::quick(), 4 << 10);
+        assert_eq!(measured.bytes, 4 << 10);
+        assert!(measured.ns_per_load > 0.0);
+    }
+}
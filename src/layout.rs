@@ -0,0 +1,764 @@
+use std::{
+    marker::PhantomData,
+    ops::{Mul, Range},
+    rc::Rc,
+    slice,
+};
+
+use crate::{
+    aligned::AlignedBuf, cache::Footprint, cachesim::Access, rng::Rng, simd::StdFloat, trace,
+    utils::Cluster,
+};
+
+/// A container holding `n` elements of `F` `Cluster` fields each.
+///
+/// Implementations differ only in where field `f` of element `i` lives in
+/// memory, so the same kernel can be run over every layout.
+pub trait Layout<const F: usize>: Footprint + Sized {
+    /// short name used in benchmark names and reports
+    const NAME: &'static str;
+
+    fn with_capacity(n: usize) -> Self;
+
+    fn push(&mut self, row: [Cluster; F]);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// field `field` of element `i`
+    fn get(&self, i: usize, field: usize) -> Cluster;
+
+    /// [`Layout::get`] without the bounds check, for layouts that can skip it
+    ///
+    /// # Safety
+    ///
+    /// `i` has to be below [`len`](Layout::len) and `field` below `F`.
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, i: usize, field: usize) -> Cluster {
+        self.get(i, field)
+    }
+
+    /// where [`Layout::get`] reads field `field` of element `i`
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster;
+}
+
+/// value of field `field` of `fields` for element `i`, shared by all layouts
+/// so that every layout of the same field count holds identical data
+#[inline]
+pub fn init_field(i: usize, field: usize, fields: usize) -> Cluster {
+    let v = match (field, fields) {
+        (0, _) => i,
+        (1, _) => i / 3,
+        (3, 5) => i + 3,
+        (5, _) => i + 5,
+        (f, _) => i * f,
+    };
+    Cluster::splat(v as f32)
+}
+
+pub fn make_row<const F: usize>(i: usize) -> [Cluster; F] {
+    std::array::from_fn(|field| init_field(i, field, F))
+}
+
+pub fn make_layout<L: Layout<F>, const F: usize>(seed: usize, n: usize) -> L {
+    let mut layout = L::with_capacity(n);
+    for i in seed..seed + n {
+        layout.push(make_row(i));
+    }
+    layout
+}
+
+/// indices `0..F`, i.e. a kernel touching every field
+pub fn dense<const F: usize>() -> [usize; F] {
+    std::array::from_fn(|field| field)
+}
+
+/// fields touched by the sparse 7 field kernel
+pub const SPARSE_7: [usize; 4] = [0, 2, 3, 6];
+
+/// fields touched by the sparse 9 field kernel
+pub const SPARSE_9: [usize; 6] = [0, 2, 5, 6, 7, 8];
+
+/// `v[0].mul_add(v[1], v[2]).mul_add(v[3], v[4])...`, with a trailing `mul`
+/// if `K` is even
+#[inline(always)]
+pub fn chain<const K: usize>(v: [Cluster; K]) -> Cluster {
+    let mut acc = v[0];
+    let mut k = 1;
+    while k + 1 < K {
+        acc = acc.mul_add(v[k], v[k + 1]);
+        k += 2;
+    }
+    if k < K {
+        acc = acc.mul(v[k]);
+    }
+    acc
+}
+
+/// `v[0].mul(v[1]).mul_add(v[2], v[3])...`, the leading multiply of the
+/// sparse 9 field kernel, for an even `K`
+#[inline(always)]
+pub fn chain_mul<const K: usize>(v: [Cluster; K]) -> Cluster {
+    let mut acc = v[0].mul(v[1]);
+    let mut k = 2;
+    while k + 1 < K {
+        acc = acc.mul_add(v[k], v[k + 1]);
+        k += 2;
+    }
+    acc
+}
+
+/// Combines the `fields` of every element with [`chain`], or [`chain_mul`]
+/// for [`SPARSE_9`], writes the result per element and returns the sum over
+/// all elements.
+#[inline(always)]
+pub fn compute<L: Layout<F>, const F: usize, const K: usize>(
+    data_set: &L,
+    fields: &[usize; K],
+    result: &mut [Cluster],
+) -> Cluster {
+    if fields[..] == SPARSE_9 {
+        compute_with(data_set, fields, result, chain_mul)
+    } else {
+        compute_with(data_set, fields, result, chain)
+    }
+}
+
+#[inline(always)]
+fn compute_with<L: Layout<F>, const F: usize, const K: usize>(
+    data_set: &L,
+    fields: &[usize; K],
+    result: &mut [Cluster],
+    kernel: impl Fn([Cluster; K]) -> Cluster,
+) -> Cluster {
+    assert!(
+        fields.iter().all(|&field| field < F),
+        "no fields {fields:?} of {F}"
+    );
+    let mut sum = Cluster::splat(0.0);
+    for (i, r) in result.iter_mut().take(data_set.len()).enumerate() {
+        // SAFETY: i is below len by the take, every field below F by the
+        // assert
+        let tmp = kernel(fields.map(|field| unsafe { data_set.get_unchecked(i, field) }));
+        trace::write(r, tmp);
+        sum += tmp;
+    }
+    sum
+}
+
+/// The loads and stores of [`compute`] with the same arguments, in program
+/// order: the `fields` of every element, then its result.
+pub fn compute_accesses<L: Layout<F>, const F: usize, const K: usize>(
+    data_set: &L,
+    fields: &[usize; K],
+    result: &[Cluster],
+    mut visit: impl FnMut(Access),
+) {
+    const SIZE: usize = size_of::<Cluster>();
+    for (i, r) in result.iter().take(data_set.len()).enumerate() {
+        for &field in fields {
+            visit(Access::read(data_set.ptr(i, field) as usize, SIZE));
+        }
+        visit(Access::write(r as *const Cluster as usize, SIZE));
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// array of structs: `Vec<S>` where `S` has `F` `Cluster` fields
+pub struct AoS<const F: usize> {
+    rows: Vec<[Cluster; F]>,
+}
+
+impl<const F: usize> Footprint for AoS<F> {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        self.rows.ranges(out);
+    }
+}
+
+impl<const F: usize> Layout<F> for AoS<F> {
+    const NAME: &'static str = "aos";
+
+    fn with_capacity(n: usize) -> Self {
+        AoS {
+            rows: Vec::with_capacity(n),
+        }
+    }
+
+    fn push(&mut self, row: [Cluster; F]) {
+        self.rows.push(row);
+    }
+
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize, field: usize) -> Cluster {
+        trace::read(&self.rows[i][field])
+    }
+
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster {
+        &self.rows[i][field]
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// struct of arrays: one `Vec<Cluster>` per field
+pub struct SoA<const F: usize> {
+    columns: [Vec<Cluster>; F],
+}
+
+impl<const F: usize> Footprint for SoA<F> {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        for column in &self.columns {
+            column.ranges(out);
+        }
+    }
+}
+
+impl<const F: usize> Layout<F> for SoA<F> {
+    const NAME: &'static str = "soa";
+
+    fn with_capacity(n: usize) -> Self {
+        SoA {
+            columns: std::array::from_fn(|_| Vec::with_capacity(n)),
+        }
+    }
+
+    fn push(&mut self, row: [Cluster; F]) {
+        for (column, v) in self.columns.iter_mut().zip(row) {
+            column.push(v);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.columns.first().map_or(0, Vec::len)
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize, field: usize) -> Cluster {
+        trace::read(&self.columns[field][i])
+    }
+
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster {
+        &self.columns[field][i]
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// cache lines of padding [`PlacedSoA`] puts before a column at most, when
+/// placing at random
+pub const PAD_LINES: usize = 1024;
+
+/// Struct of arrays with the columns at controlled offsets of one
+/// [`AlignedBuf`], shared by all datasets built by [`PlacedSoA::datasets`].
+/// With `ALIGN > 0` the columns of a dataset lie a multiple of `ALIGN` bytes
+/// apart, so that all of them map to the same cache sets. With `ALIGN == 0`
+/// they follow each other with a random number of cache lines, below
+/// [`PAD_LINES`], before every column, drawn from the seed of the datasets
+/// and the index of each.
+pub struct PlacedSoA<const F: usize, const ALIGN: usize> {
+    buf: Rc<AlignedBuf>,
+    /// byte offset of every column in `buf`
+    offsets: [usize; F],
+    len: usize,
+    capacity: usize,
+}
+
+impl<const F: usize, const ALIGN: usize> PlacedSoA<F, ALIGN> {
+    /// The column offsets of `m` datasets of `n` elements, the bytes and the
+    /// alignment of the buffer holding them. With `ALIGN > 0` column `k` of
+    /// every dataset lies in the `k`th of `F` regions `ALIGN` aligned, the
+    /// datasets one after the other within each.
+    fn place(m: usize, n: usize, seed: u64) -> (Vec<[usize; F]>, usize, usize) {
+        const LINE: usize = 64;
+        let column = (n * size_of::<Cluster>()).next_multiple_of(LINE);
+        if ALIGN > 0 {
+            let stride = (m * column).next_multiple_of(ALIGN).max(ALIGN);
+            let offsets = (0..m)
+                .map(|j| std::array::from_fn(|field| field * stride + j * column))
+                .collect();
+            (offsets, F * stride, ALIGN)
+        } else {
+            let mut end = 0;
+            let offsets = (0..m)
+                .map(|j| {
+                    let mut rng = Rng::new(seed ^ j as u64);
+                    std::array::from_fn(|_| {
+                        let offset = end + rng.below(PAD_LINES) * LINE;
+                        end = offset + column;
+                        offset
+                    })
+                })
+                .collect();
+            (offsets, end, 4096)
+        }
+    }
+
+    /// `m` datasets of `n` elements in one buffer, dataset `j` holding the
+    /// rows of [`make_layout`] with seed `j`. The same `seed` gives the same
+    /// random placement.
+    pub fn datasets(m: usize, n: usize, seed: u64) -> Vec<Self> {
+        let (offsets, size, align) = Self::place(m, n, seed);
+        let mut buf = AlignedBuf::new(size, align);
+        let base = buf.as_mut_ptr();
+        for (j, offsets) in offsets.iter().enumerate() {
+            for i in 0..n {
+                for (offset, v) in offsets.iter().zip(make_row::<F>(j + i)) {
+                    // SAFETY: element i of the column is within buf and aligned
+                    unsafe {
+                        base.add(offset + i * size_of::<Cluster>())
+                            .cast::<Cluster>()
+                            .write(v)
+                    };
+                }
+            }
+        }
+        let buf = Rc::new(buf);
+        offsets
+            .into_iter()
+            .map(|offsets| PlacedSoA {
+                buf: Rc::clone(&buf),
+                offsets,
+                len: n,
+                capacity: n,
+            })
+            .collect()
+    }
+
+    fn column(&self, field: usize) -> &[Cluster] {
+        // SAFETY: the column lies within buf, is aligned to a Cluster and its
+        // first len elements are written
+        unsafe {
+            slice::from_raw_parts(self.buf.as_ptr().add(self.offsets[field]).cast(), self.len)
+        }
+    }
+
+    /// byte offset of every column from the start of the allocation
+    pub fn offsets(&self) -> [usize; F] {
+        self.offsets
+    }
+}
+
+impl<const F: usize, const ALIGN: usize> Footprint for PlacedSoA<F, ALIGN> {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        for field in 0..F {
+            self.column(field).ranges(out);
+        }
+    }
+}
+
+impl<const F: usize, const ALIGN: usize> Layout<F> for PlacedSoA<F, ALIGN> {
+    const NAME: &'static str = "soa";
+
+    /// a single dataset in a buffer of its own, placed with seed 0
+    fn with_capacity(n: usize) -> Self {
+        let (offsets, size, align) = Self::place(1, n, 0);
+        PlacedSoA {
+            buf: Rc::new(AlignedBuf::new(size, align)),
+            offsets: offsets[0],
+            len: 0,
+            capacity: n,
+        }
+    }
+
+    fn push(&mut self, row: [Cluster; F]) {
+        assert!(self.len < self.capacity, "PlacedSoA is full");
+        let buf = Rc::get_mut(&mut self.buf).expect("PlacedSoA shares its buffer");
+        let base = buf.as_mut_ptr();
+        for (offset, v) in self.offsets.iter().zip(row) {
+            // SAFETY: element len of the column is within buf and aligned
+            unsafe {
+                base.add(offset + self.len * size_of::<Cluster>())
+                    .cast::<Cluster>()
+                    .write(v)
+            };
+        }
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize, field: usize) -> Cluster {
+        trace::read(&self.column(field)[i])
+    }
+
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster {
+        &self.column(field)[i]
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// array of structs as one flat `Vec<Cluster>`, element `i` starts at `i * F`
+pub struct Blob<const F: usize> {
+    data: Vec<Cluster>,
+}
+
+impl<const F: usize> Footprint for Blob<F> {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        self.data.ranges(out);
+    }
+}
+
+impl<const F: usize> Layout<F> for Blob<F> {
+    const NAME: &'static str = "blob";
+
+    fn with_capacity(n: usize) -> Self {
+        Blob {
+            data: Vec::with_capacity(n * F),
+        }
+    }
+
+    fn push(&mut self, row: [Cluster; F]) {
+        self.data.extend_from_slice(&row);
+    }
+
+    fn len(&self) -> usize {
+        self.data.len() / F
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize, field: usize) -> Cluster {
+        trace::read(&self.data[i * F + field])
+    }
+
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster {
+        &self.data[i * F + field]
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// array of structs of arrays: blocks of `K` elements, each block holding `K`
+/// consecutive `Cluster`s per field
+pub struct AoSoA<const F: usize, const K: usize> {
+    blocks: Vec<[[Cluster; K]; F]>,
+    len: usize,
+}
+
+impl<const F: usize, const K: usize> Footprint for AoSoA<F, K> {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        self.blocks.ranges(out);
+    }
+}
+
+impl<const F: usize, const K: usize> Layout<F> for AoSoA<F, K> {
+    const NAME: &'static str = "aosoa";
+
+    fn with_capacity(n: usize) -> Self {
+        const { assert!(K > 0, "AoSoA blocks of 0 elements") };
+        AoSoA {
+            blocks: Vec::with_capacity(n.div_ceil(K)),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, row: [Cluster; F]) {
+        if self.len.is_multiple_of(K) {
+            self.blocks.push([[Cluster::splat(0.0); K]; F]);
+        }
+        let block = self.blocks.last_mut().unwrap();
+        for (field, v) in row.into_iter().enumerate() {
+            block[field][self.len % K] = v;
+        }
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize, field: usize) -> Cluster {
+        trace::read(&self.blocks[i / K][field][i % K])
+    }
+
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster {
+        &self.blocks[i / K][field][i % K]
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// A layout whose allocations are arrays of `Cluster`s, so that it can be
+/// rebuilt by [`Realigned`] in allocations of a chosen alignment.
+pub trait Slots<const F: usize>: Layout<F> {
+    /// `Cluster`s of every array holding `n` elements
+    fn arrays(n: usize) -> Vec<usize>;
+
+    /// array and index in it of field `field` of element `i` of `n`
+    fn slot(n: usize, i: usize, field: usize) -> (usize, usize);
+}
+
+impl<const F: usize> Slots<F> for AoS<F> {
+    fn arrays(n: usize) -> Vec<usize> {
+        vec![n * F]
+    }
+
+    fn slot(_: usize, i: usize, field: usize) -> (usize, usize) {
+        (0, i * F + field)
+    }
+}
+
+impl<const F: usize> Slots<F> for SoA<F> {
+    fn arrays(n: usize) -> Vec<usize> {
+        vec![n; F]
+    }
+
+    fn slot(_: usize, i: usize, field: usize) -> (usize, usize) {
+        (field, i)
+    }
+}
+
+impl<const F: usize> Slots<F> for Blob<F> {
+    fn arrays(n: usize) -> Vec<usize> {
+        vec![n * F]
+    }
+
+    fn slot(_: usize, i: usize, field: usize) -> (usize, usize) {
+        (0, i * F + field)
+    }
+}
+
+impl<const F: usize, const K: usize> Slots<F> for AoSoA<F, K> {
+    fn arrays(n: usize) -> Vec<usize> {
+        vec![n.div_ceil(K) * K * F]
+    }
+
+    fn slot(_: usize, i: usize, field: usize) -> (usize, usize) {
+        (0, (i / K * F + field) * K + i % K)
+    }
+}
+
+/// `L` with every array in its own allocation, starting `OFFSET` bytes past
+/// a multiple of `ALIGN`. Loads go through `read_unaligned`, so any offset
+/// works, including ones that split `Cluster`s over two cache lines.
+pub struct Realigned<L, const F: usize, const ALIGN: usize, const OFFSET: usize> {
+    arrays: Vec<AlignedBuf>,
+    len: usize,
+    capacity: usize,
+    layout: PhantomData<fn() -> L>,
+}
+
+impl<L: Slots<F>, const F: usize, const ALIGN: usize, const OFFSET: usize>
+    Realigned<L, F, ALIGN, OFFSET>
+{
+    /// where field `field` of element `i` lives, whether or not written
+    #[inline(always)]
+    fn slot_ptr(&self, i: usize, field: usize) -> *const Cluster {
+        let (array, index) = L::slot(self.capacity, i, field);
+        // SAFETY: index is within the array L::arrays sized
+        unsafe {
+            self.arrays[array]
+                .as_ptr()
+                .add(OFFSET + index * size_of::<Cluster>())
+                .cast()
+        }
+    }
+}
+
+impl<L: Slots<F>, const F: usize, const ALIGN: usize, const OFFSET: usize> Footprint
+    for Realigned<L, F, ALIGN, OFFSET>
+{
+    /// whole allocations, so that the offset of the data in them survives
+    /// the remapping of the simulator
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        for array in &self.arrays {
+            array.ranges(out);
+        }
+    }
+}
+
+impl<L: Slots<F>, const F: usize, const ALIGN: usize, const OFFSET: usize> Layout<F>
+    for Realigned<L, F, ALIGN, OFFSET>
+{
+    const NAME: &'static str = L::NAME;
+
+    fn with_capacity(n: usize) -> Self {
+        let arrays = L::arrays(n)
+            .into_iter()
+            .map(|len| AlignedBuf::new(OFFSET + len * size_of::<Cluster>(), ALIGN))
+            .collect();
+        Realigned {
+            arrays,
+            len: 0,
+            capacity: n,
+            layout: PhantomData,
+        }
+    }
+
+    fn push(&mut self, row: [Cluster; F]) {
+        assert!(self.len < self.capacity, "Realigned is full");
+        for (field, v) in row.into_iter().enumerate() {
+            // SAFETY: the slot is within its array, written unaligned
+            unsafe { self.slot_ptr(self.len, field).cast_mut().write_unaligned(v) };
+        }
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize, field: usize) -> Cluster {
+        // SAFETY: ptr checks that element i is written
+        unsafe { trace::read_unaligned(self.ptr(i, field)) }
+    }
+
+    /// No bounds check, which [`compute`] does not need in its timed loop
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, i: usize, field: usize) -> Cluster {
+        debug_assert!(i < self.len && field < F, "no field {field} of element {i}");
+        // SAFETY: element i is written, as the caller guarantees
+        unsafe { trace::read_unaligned(self.slot_ptr(i, field)) }
+    }
+
+    #[inline(always)]
+    fn ptr(&self, i: usize, field: usize) -> *const Cluster {
+        assert!(i < self.len && field < F, "no field {field} of element {i}");
+        self.slot_ptr(i, field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::footprint;
+
+    #[test]
+    fn test_chain() {
+        let v: [Cluster; 8] = make_row(3);
+        let expected = v[0]
+            .mul_add(v[1], v[2])
+            .mul_add(v[3], v[4])
+            .mul_add(v[5], v[6])
+            .mul(v[7]);
+        assert_eq!(chain(v), expected);
+        // field d of the original S5 is i + 3, of every wider struct i * 3
+        let s5: [Cluster; 5] = make_row(4);
+        assert_eq!((s5[3], v[3]), (Cluster::splat(7.0), Cluster::splat(9.0)));
+    }
+
+    #[test]
+    fn test_sparse_9() {
+        let mut result = vec![Cluster::splat(0.0); 10];
+        let mut expected = vec![Cluster::splat(0.0); 10];
+        let mut sum = Cluster::splat(0.0);
+        for (j, r) in expected.iter_mut().enumerate() {
+            let [a, _, c, _, _, f, g, h, i] = make_row::<9>(3 + j);
+            *r = a.mul(c).mul_add(f, g).mul_add(h, i);
+            sum += *r;
+        }
+        let soa: SoA<9> = make_layout(3, 10);
+        assert_eq!(compute(&soa, &SPARSE_9, &mut result), sum);
+        assert_eq!(result, expected);
+        let blob: Blob<9> = make_layout(3, 10);
+        assert_eq!(compute(&blob, &SPARSE_9, &mut result), sum);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_placed() {
+        let fields = dense::<9>();
+        let mut result = vec![Cluster::splat(0.0); 10];
+        let soa: SoA<9> = make_layout(0, 10);
+        let expected = compute(&soa, &fields, &mut result);
+
+        let page: PlacedSoA<9, 4096> = make_layout(0, 10);
+        let mega: PlacedSoA<9, { 1 << 20 }> = make_layout(0, 10);
+        let random: PlacedSoA<9, 0> = make_layout(0, 10);
+        assert_eq!(compute(&page, &fields, &mut result), expected);
+        assert_eq!(compute(&mega, &fields, &mut result), expected);
+        assert_eq!(compute(&random, &fields, &mut result), expected);
+
+        for field in 0..9 {
+            assert_eq!(page.ptr(0, field) as usize % 4096, 0);
+            assert_eq!(mega.ptr(0, field) as usize % (1 << 20), 0);
+            assert_eq!(random.ptr(0, field) as usize % 64, 0);
+        }
+        assert_eq!(page.offsets()[1], 4096);
+        assert_eq!(mega.offsets()[8], 8 << 20);
+        let other: PlacedSoA<9, 0> = make_layout(0, 10);
+        assert_eq!(random.offsets(), other.offsets());
+        let seeded = PlacedSoA::<9, 0>::datasets(2, 10, 5);
+        assert_ne!(seeded[0].offsets(), seeded[1].offsets());
+        let again = PlacedSoA::<9, 0>::datasets(2, 10, 5);
+        assert_eq!(seeded[1].offsets(), again[1].offsets());
+        assert!(random.offsets().windows(2).all(|w| w[1] >= w[0] + 320));
+
+        // 300 datasets share 9 regions of 3M, not 9M each
+        let datasets = PlacedSoA::<9, { 1 << 20 }>::datasets(300, 256, 0);
+        assert_eq!(datasets[0].buf.len(), 9 * (3 << 20));
+        for (j, dataset) in datasets.iter().enumerate() {
+            let soa: SoA<9> = make_layout(j, 256);
+            let expected = compute(&soa, &fields, &mut result);
+            assert_eq!(compute(dataset, &fields, &mut result), expected);
+            let offsets = dataset.offsets();
+            assert!(offsets.iter().all(|o| (o - offsets[0]) % (1 << 20) == 0));
+        }
+    }
+
+    fn test_realigned<L: Slots<9>>() {
+        let fields = dense::<9>();
+        let mut result = vec![Cluster::splat(0.0); 10];
+        let vec: L = make_layout(0, 10);
+        let expected = compute(&vec, &fields, &mut result);
+
+        let line: Realigned<L, 9, 64, 0> = make_layout(0, 10);
+        let page: Realigned<L, 9, 4096, 0> = make_layout(0, 10);
+        let off4: Realigned<L, 9, 64, 4> = make_layout(0, 10);
+        let off16: Realigned<L, 9, 64, 16> = make_layout(0, 10);
+        assert_eq!(compute(&line, &fields, &mut result), expected);
+        assert_eq!(compute(&page, &fields, &mut result), expected);
+        assert_eq!(compute(&off4, &fields, &mut result), expected);
+        assert_eq!(compute(&off16, &fields, &mut result), expected);
+        let sparse = compute(&vec, &SPARSE_9, &mut result);
+        assert_eq!(compute(&off16, &SPARSE_9, &mut result), sparse);
+
+        assert_eq!(line.ptr(0, 0) as usize % 64, 0);
+        assert_eq!(page.ptr(0, 0) as usize % 4096, 0);
+        assert_eq!(off4.ptr(0, 0) as usize % 64, 4);
+        // a single array keeps the offsets of the original
+        if L::arrays(10).len() == 1 {
+            let from = |p: *const Cluster, start: *const Cluster| p as usize - start as usize;
+            for i in 0..10 {
+                for field in 0..9 {
+                    assert_eq!(
+                        from(off4.ptr(i, field), off4.ptr(0, 0)),
+                        from(vec.ptr(i, field), vec.ptr(0, 0))
+                    );
+                }
+            }
+        }
+        let ranges = footprint(&off4);
+        assert_eq!(ranges[0].start % 64, 0);
+        assert_eq!(off4.ptr(0, 0) as usize - ranges[0].start, 4);
+    }
+
+    #[test]
+    fn test_realigned_layouts() {
+        test_realigned::<AoS<9>>();
+        test_realigned::<SoA<9>>();
+        test_realigned::<Blob<9>>();
+        test_realigned::<AoSoA<9, 4>>();
+        assert_eq!(AoSoA::<9, 4>::arrays(10), [108]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no field 0 of element 10")]
+    fn test_realigned_bounds() {
+        let off4: Realigned<SoA<9>, 9, 64, 4> = make_layout(0, 10);
+        off4.get(10, 0);
+    }
+}
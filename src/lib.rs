@@ -1,1039 +1,272 @@
-#![feature(portable_simd)]
-#![feature(test)]
-#[deny(soft_unstable)]
-extern crate test;
+#![cfg_attr(nightly, feature(portable_simd))]
+extern crate self as cache_bench;
 
+pub mod aligned;
+pub mod baseline;
 pub mod bench_pointer_arithmetic;
 pub mod bench_runtime;
+pub mod bench_stride;
+pub mod cache;
+pub mod cachesim;
+pub mod cli;
+pub mod clock;
+pub mod columns;
+pub mod counters;
+pub mod harness;
+pub mod json;
+pub mod latency;
+pub mod layout;
+pub mod registry;
+pub mod report;
+pub mod rng;
+pub mod roofline;
+pub mod simd;
+pub mod stats;
+pub mod stream;
+pub mod sweep;
+pub mod topology;
+pub mod trace;
 pub mod utils;
 
+pub use cache_bench_derive::SoA;
+
 #[cfg(test)]
 mod tests {
-    use std::{
-        hint::black_box,
-        ops::Mul,
-        simd::{Simd, StdFloat},
+    use crate::cache::{CacheMode, Eviction};
+    use crate::cachesim::{simulate_layout, Hierarchy, Replacement};
+    use crate::cli::{self, Options};
+    use crate::columns::{compute_rows, compute_s7, make_aos, make_s7, make_soa};
+    use crate::harness;
+    use crate::layout::{
+        compute, dense, make_layout, AoS, AoSoA, Blob, Layout, SoA, SPARSE_7, SPARSE_9,
     };
-    use test::Bencher;
-
+    use crate::registry::Filter;
+    use crate::stats::{compare, Summary};
+    use crate::sweep::{sweep_layout, Series, SweepConfig};
+    use crate::topology::CacheTopology;
     use crate::utils::*;
 
-    /// data size
-    const N_SMALL: usize = 9;
-
-    /// data size
-    const N_BIG: usize = 256;
-
-    #[inline(always)]
-    fn bench<T>(
-        b: &mut Bencher,
-        data_sets: &[T],
-        result: &mut [Cluster],
-        f: impl Fn(&T, &mut [Cluster]),
-    ) {
-        let mut i = 0;
-        b.iter(|| {
-            let data_set = &data_sets[i];
-            black_box(f(data_set, result));
-            i += 7;
-            if i >= M {
-                i -= M;
-            }
-        });
-    }
-
-    // ------------------------------------------------------------------------
-
-    struct S3 {
-        a: Cluster,
-        b: Cluster,
-        c: Cluster,
-    }
-
-    fn make_aos3(seed: usize, n: usize) -> Vec<S3> {
-        let mut v = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            v.push(S3 {
-                a: Cluster::splat(i as f32),
-                b: Cluster::splat((i / 3) as f32),
-                c: Cluster::splat((i * 2) as f32),
-            });
-        }
-        v
-    }
-
-    fn compute_aos3(data_set: &Vec<S3>, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        for (s3, r) in data_set.iter().zip(result.iter_mut()) {
-            let tmp = s3.a.mul_add(s3.b, s3.c);
-            *r = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_aos3_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos3(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos3(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_3_small_aos(b: &mut Bencher) {
-        bench_aos3_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_3_big_aos(b: &mut Bencher) {
-        bench_aos3_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-
-    struct SoA3 {
-        a: Vec<Cluster>,
-        b: Vec<Cluster>,
-        c: Vec<Cluster>,
-    }
-
-    fn make_soa3(seed: usize, n: usize) -> SoA3 {
-        let mut b = Vec::with_capacity(n);
-        let mut c = Vec::with_capacity(n);
-        let mut a = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            a.push(Cluster::splat(i as f32));
-            b.push(Cluster::splat((i / 3) as f32));
-            c.push(Cluster::splat((i * 2) as f32));
-        }
-        SoA3 { a, b, c }
-    }
-
-    fn bench_soa3_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa3(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_soa3(data_set, result));
-        });
-    }
-
-    fn compute_soa3(data_set: &SoA3, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i].mul_add(data_set.b[i], data_set.c[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    #[bench]
-    fn bench_3_small_soa(b: &mut Bencher) {
-        bench_soa3_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_3_big_soa(b: &mut Bencher) {
-        bench_soa3_impl(b, N_BIG);
+    fn test_layouts<const F: usize, const K: usize>(fields: &[usize; K]) {
+        let aos: AoS<F> = make_layout(0, 10);
+        let soa: SoA<F> = make_layout(0, 10);
+        let blob: Blob<F> = make_layout(0, 10);
+        let mut result = vec![Cluster::splat(0.0); 10];
+        let tmp = compute(&aos, fields, &mut result);
+        assert_eq!(tmp, compute(&soa, fields, &mut result));
+        assert_eq!(tmp, compute(&blob, fields, &mut result));
+        let aosoa: AoSoA<F, 4> = make_layout(0, 10);
+        assert_eq!(tmp, compute(&aosoa, fields, &mut result));
     }
 
     #[test]
     fn test_3_benchmarks() {
-        let aos3 = make_aos3(0, 10);
-        let soa3 = make_soa3(0, 10);
-        let mut result = vec![Cluster::splat(0.0); 10];
-        assert_eq!(
-            compute_aos3(&aos3, &mut result),
-            compute_soa3(&soa3, &mut result)
-        );
-    }
-
-    // ------------------------------------------------------------------------
-    struct S5 {
-        a: Cluster,
-        b: Cluster,
-        c: Cluster,
-        d: Cluster,
-        e: Cluster,
-    }
-
-    fn make_aos5(seed: usize, n: usize) -> Vec<S5> {
-        let mut v = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            v.push(S5 {
-                a: Cluster::splat(i as f32),
-                b: Cluster::splat((i / 3) as f32),
-                c: Cluster::splat((i * 2) as f32),
-                d: Cluster::splat((i + 3) as f32),
-                e: Cluster::splat((i * 4) as f32),
-            });
-        }
-        v
-    }
-
-    fn compute_aos5(data_set: &Vec<S5>, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        for (s5, r) in data_set.iter().zip(result.iter_mut()) {
-            let tmp = s5.a.mul_add(s5.b, s5.c).mul_add(s5.d, s5.e);
-            *r = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_aos5_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos5(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos5(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_5small_aos(b: &mut Bencher) {
-        bench_aos5_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_5big_aos(b: &mut Bencher) {
-        bench_aos5_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-
-    struct SoA5 {
-        a: Vec<Cluster>,
-        b: Vec<Cluster>,
-        c: Vec<Cluster>,
-        d: Vec<Cluster>,
-        e: Vec<Cluster>,
-    }
-
-    fn make_soa5(seed: usize, n: usize) -> SoA5 {
-        let mut b = Vec::with_capacity(n);
-        let mut a = Vec::with_capacity(n);
-        let mut c = Vec::with_capacity(n);
-        let mut e = Vec::with_capacity(n);
-        let mut d = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            a.push(Cluster::splat(i as f32));
-            b.push(Cluster::splat((i / 3) as f32));
-            c.push(Cluster::splat((i * 2) as f32));
-            d.push(Cluster::splat((i + 3) as f32));
-            e.push(Cluster::splat((i * 4) as f32));
-        }
-        SoA5 { a, b, c, d, e }
-    }
-
-    fn bench_soa5_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa5(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_soa5(data_set, result));
-        });
-    }
-
-    fn compute_soa5(data_set: &SoA5, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i]
-                .mul_add(data_set.b[i], data_set.c[i])
-                .mul_add(data_set.d[i], data_set.e[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
+        test_layouts::<3, 3>(&dense());
     }
 
-    #[bench]
-    fn bench_5small_soa(b: &mut Bencher) {
-        bench_soa5_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_5big_soa(b: &mut Bencher) {
-        bench_soa5_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-
-    struct AoSblob5 {
-        data: Vec<Cluster>,
-    }
-
-    fn make_aos5_blob(seed: usize, n: usize) -> AoSblob5 {
-        let mut data = Vec::with_capacity(n * 5);
-        for i in seed..seed + n {
-            data.push(Cluster::splat(i as f32));
-            data.push(Cluster::splat((i / 3) as f32));
-            data.push(Cluster::splat((i * 2) as f32));
-            data.push(Cluster::splat((i + 3) as f32));
-            data.push(Cluster::splat((i * 4) as f32));
-        }
-        AoSblob5 { data }
-    }
-
-    fn compute_aos_blob5(data_set: &AoSblob5, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let mut i = 0;
-        for r in result.iter_mut() {
-            let tmp = data_set.data[i]
-                .mul_add(data_set.data[i + 1], data_set.data[i + 2])
-                .mul_add(data_set.data[i + 3], data_set.data[i + 4]);
-            *r = tmp;
-            sum += tmp;
-            i += 5;
-        }
-        sum
-    }
-
-    fn bench_aos_blob5_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos5_blob(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos_blob5(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_5small_blob_aos(b: &mut Bencher) {
-        bench_aos_blob5_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_5big_blob_aos(b: &mut Bencher) {
-        bench_aos_blob5_impl(b, N_BIG);
+    #[test]
+    fn test_4_benchmarks() {
+        test_layouts::<4, 4>(&dense());
     }
 
     #[test]
     fn test_5_benchmarks() {
-        let aos5 = make_aos5(0, 10);
-        let soa5 = make_soa5(0, 10);
-        let aosblob5 = make_aos5_blob(0, 10);
-        let mut result = vec![Cluster::splat(0.0); 10];
-        assert_eq!(
-            compute_aos5(&aos5, &mut result),
-            compute_soa5(&soa5, &mut result)
-        );
-        assert_eq!(
-            compute_aos5(&aos5, &mut result),
-            compute_aos_blob5(&aosblob5, &mut result)
-        );
-    }
-
-    // ------------------------------------------------------------------------
-
-    struct S7 {
-        a: Cluster,
-        b: Cluster,
-        c: Cluster,
-        d: Cluster,
-        e: Cluster,
-        f: Cluster,
-        g: Cluster,
-    }
-
-    fn make_aos7(seed: usize, n: usize) -> Vec<S7> {
-        let mut v = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            v.push(S7 {
-                a: Cluster::splat(i as f32),
-                b: Cluster::splat((i / 3) as f32),
-                c: Cluster::splat((i * 2) as f32),
-                d: Cluster::splat((i * 3) as f32),
-                e: Cluster::splat((i * 4) as f32),
-                f: Cluster::splat((i + 5) as f32),
-                g: Cluster::splat((i * 6) as f32),
-            });
-        }
-        v
-    }
-
-    fn compute_aos7(data_set: &Vec<S7>, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        for (s7, r) in data_set.iter().zip(result.iter_mut()) {
-            let tmp =
-                s7.a.mul_add(s7.b, s7.c)
-                    .mul_add(s7.d, s7.e)
-                    .mul_add(s7.f, s7.g);
-            *r = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_aos7_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos7(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos7(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_7small_aos(b: &mut Bencher) {
-        bench_aos7_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_7big_aos(b: &mut Bencher) {
-        bench_aos7_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-    struct SoA7 {
-        a: Vec<Cluster>,
-        b: Vec<Cluster>,
-        c: Vec<Cluster>,
-        d: Vec<Cluster>,
-        e: Vec<Cluster>,
-        f: Vec<Cluster>,
-        g: Vec<Cluster>,
-    }
-
-    fn make_soa7(seed: usize, n: usize) -> SoA7 {
-        let mut c = Vec::with_capacity(n);
-        let mut b = Vec::with_capacity(n);
-        let mut e = Vec::with_capacity(n);
-        let mut d = Vec::with_capacity(n);
-        let mut a = Vec::with_capacity(n);
-        let mut f = Vec::with_capacity(n);
-        let mut g = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            a.push(Cluster::splat(i as f32));
-            b.push(Cluster::splat((i / 3) as f32));
-            c.push(Cluster::splat((i * 2) as f32));
-            d.push(Cluster::splat((i * 3) as f32));
-            e.push(Cluster::splat((i * 4) as f32));
-            f.push(Cluster::splat((i + 5) as f32));
-            g.push(Cluster::splat((i * 6) as f32));
-        }
-        SoA7 {
-            a,
-            b,
-            c,
-            d,
-            e,
-            f,
-            g,
-        }
-    }
-
-    fn bench_soa7_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa7(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_soa7(data_set, result));
-        });
-    }
-
-    fn compute_soa7(data_set: &SoA7, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i]
-                .mul_add(data_set.b[i], data_set.c[i])
-                .mul_add(data_set.d[i], data_set.e[i])
-                .mul_add(data_set.f[i], data_set.g[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    #[bench]
-    fn bench_7small_soa(b: &mut Bencher) {
-        bench_soa7_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_7big_soa(b: &mut Bencher) {
-        bench_soa7_impl(b, N_BIG);
+        test_layouts::<5, 5>(&dense());
     }
 
     #[test]
     fn test_7_benchmarks() {
-        let aos7 = make_aos7(0, 10);
-        let soa7 = make_soa7(0, 10);
-        let mut result = vec![Cluster::splat(0.0); 10];
-        assert_eq!(
-            compute_aos7(&aos7, &mut result),
-            compute_soa7(&soa7, &mut result)
-        );
-    }
-
-    // ------------------------------------------------------------------------
-
-    fn compute_aos7_sparse(data_set: &Vec<S7>, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        for (s7, r) in data_set.iter().zip(result.iter_mut()) {
-            let tmp = s7.a.mul_add(s7.c, s7.d).mul(s7.g);
-            *r = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_aos7_sparse_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos7(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos7_sparse(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_sparse_7small_aos(b: &mut Bencher) {
-        bench_aos7_sparse_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_sparse_7big_aos(b: &mut Bencher) {
-        bench_aos7_sparse_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-
-    fn bench_soa7_sparse_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa7(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_soa7_sparse(data_set, result));
-        });
-    }
-
-    fn compute_soa7_sparse(data_set: &SoA7, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i]
-                .mul_add(data_set.c[i], data_set.d[i])
-                .mul(data_set.g[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    #[bench]
-    fn bench_sparse_7small_soa(b: &mut Bencher) {
-        bench_soa7_sparse_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_sparse_7big_soa(b: &mut Bencher) {
-        bench_soa7_sparse_impl(b, N_BIG);
+        test_layouts::<7, 7>(&dense());
     }
 
     #[test]
     fn test_7_sparse_benchmarks() {
-        let aos7 = make_aos7(0, 10);
-        let soa7 = make_soa7(0, 10);
-        let mut result = vec![Cluster::splat(0.0); 10];
-        assert_eq!(
-            compute_aos7_sparse(&aos7, &mut result),
-            compute_soa7_sparse(&soa7, &mut result)
-        );
+        test_layouts::<7, 4>(&SPARSE_7);
     }
 
-    // ------------------------------------------------------------------------
-    struct S8 {
-        a: Cluster,
-        b: Cluster,
-        c: Cluster,
-        d: Cluster,
-        e: Cluster,
-        f: Cluster,
-        g: Cluster,
-        h: Cluster,
-    }
-
-    fn make_aos8(seed: usize, n: usize) -> Vec<S8> {
-        let mut v = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            v.push(S8 {
-                a: Cluster::splat(i as f32),
-                b: Cluster::splat((i / 3) as f32),
-                c: Cluster::splat((i * 2) as f32),
-                d: Cluster::splat((i * 3) as f32),
-                e: Cluster::splat((i * 4) as f32),
-                f: Cluster::splat((i + 5) as f32),
-                g: Cluster::splat((i * 6) as f32),
-                h: Cluster::splat((i * 7) as f32),
-            });
-        }
-        v
-    }
-
-    fn compute_aos8(data_set: &Vec<S8>, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        for (s8, r) in data_set.iter().zip(result.iter_mut()) {
-            let tmp =
-                s8.a.mul_add(s8.b, s8.c)
-                    .mul_add(s8.d, s8.e)
-                    .mul_add(s8.f, s8.g)
-                    .mul(s8.h);
-            *r = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_aos8_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos8(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos8(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_8small_aos(b: &mut Bencher) {
-        bench_aos8_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_8big_aos(b: &mut Bencher) {
-        bench_aos8_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-
-    struct SoA8 {
-        a: Vec<Cluster>,
-        b: Vec<Cluster>,
-        c: Vec<Cluster>,
-        d: Vec<Cluster>,
-        e: Vec<Cluster>,
-        f: Vec<Cluster>,
-        g: Vec<Cluster>,
-        h: Vec<Cluster>,
-    }
-
-    fn make_soa8(seed: usize, n: usize) -> SoA8 {
-        let mut c = Vec::with_capacity(n);
-        let mut b = Vec::with_capacity(n);
-        let mut e = Vec::with_capacity(n);
-        let mut d = Vec::with_capacity(n);
-        let mut a = Vec::with_capacity(n);
-        let mut f = Vec::with_capacity(n);
-        let mut h = Vec::with_capacity(n);
-        let mut g = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            a.push(Cluster::splat(i as f32));
-            b.push(Cluster::splat((i / 3) as f32));
-            c.push(Cluster::splat((i * 2) as f32));
-            d.push(Cluster::splat((i * 3) as f32));
-            e.push(Cluster::splat((i * 4) as f32));
-            f.push(Cluster::splat((i + 5) as f32));
-            g.push(Cluster::splat((i * 6) as f32));
-            h.push(Cluster::splat((i * 7) as f32));
-        }
-        SoA8 {
-            a,
-            b,
-            c,
-            d,
-            e,
-            f,
-            g,
-            h,
-        }
-    }
-
-    fn bench_soa8_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa8(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_soa8(data_set, result));
-        });
+    #[test]
+    fn test_derive_7_benchmarks() {
+        let aos = make_aos(0, 10, make_s7);
+        let soa = make_soa(0, 10, make_s7);
+        let layout: AoS<7> = make_layout(0, 10);
+        let mut result = vec![Cluster::splat(0.0); 10];
+        let tmp = compute(&layout, &dense::<7>(), &mut result);
+        assert_eq!(tmp, compute_rows(&aos, &mut result, compute_s7));
+        assert_eq!(tmp, compute_rows(&soa, &mut result, compute_s7));
     }
 
-    fn compute_soa8(data_set: &SoA8, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i]
-                .mul_add(data_set.b[i], data_set.c[i])
-                .mul_add(data_set.d[i], data_set.e[i])
-                .mul_add(data_set.f[i], data_set.g[i])
-                .mul(data_set.h[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
+    #[test]
+    fn test_8_benchmarks() {
+        test_layouts::<8, 8>(&dense());
     }
 
-    #[bench]
-
-    fn bench_8small_soa(b: &mut Bencher) {
-        bench_soa8_impl(b, N_SMALL);
+    #[test]
+    fn test_9_benchmarks() {
+        test_layouts::<9, 9>(&dense());
     }
 
-    #[bench]
-    fn bench_8big_soa(b: &mut Bencher) {
-        bench_soa8_impl(b, N_BIG);
+    #[test]
+    fn test_sparse_9_benchmarks() {
+        test_layouts::<9, 6>(&SPARSE_9);
     }
 
     // ------------------------------------------------------------------------
-    struct AoSblob8 {
-        data: Vec<Cluster>,
-    }
 
-    fn make_aos8_blob(seed: usize, n: usize) -> AoSblob8 {
-        let mut data = Vec::with_capacity(n * 8);
-        for i in seed..seed + n {
-            data.push(Cluster::splat(i as f32));
-            data.push(Cluster::splat((i / 3) as f32));
-            data.push(Cluster::splat((i * 2) as f32));
-            data.push(Cluster::splat((i * 3) as f32));
-            data.push(Cluster::splat((i * 4) as f32));
-            data.push(Cluster::splat((i + 5) as f32));
-            data.push(Cluster::splat((i * 6) as f32));
-            data.push(Cluster::splat((i * 7) as f32));
-        }
-        AoSblob8 { data }
-    }
-
-    fn compute_aos_blob8(data_set: &AoSblob8, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let mut i = 0;
-        for r in result.iter_mut() {
-            let tmp = data_set.data[i]
-                .mul_add(data_set.data[i + 1], data_set.data[i + 2])
-                .mul_add(data_set.data[i + 3], data_set.data[i + 4])
-                .mul_add(data_set.data[i + 5], data_set.data[i + 6])
-                .mul(data_set.data[i + 7]);
-            *r = tmp;
-            sum += tmp;
-            i += 8;
-        }
-        sum
-    }
-
-    fn bench_aos_blob8_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos8_blob(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos_blob8(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_8small_blob_aos(b: &mut Bencher) {
-        bench_aos_blob8_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_8big_blob_aos(b: &mut Bencher) {
-        bench_aos_blob8_impl(b, N_BIG);
-    }
-
-    #[test]
-    fn test_8_benchmarks() {
-        let aos8 = make_aos8(0, 10);
-        let soa8 = make_soa8(0, 10);
-        let aosblob8 = make_aos8_blob(0, 10);
+    fn test_aosoa<const K: usize>() {
+        let aos: AoS<9> = make_layout(0, 10);
+        let aosoa: AoSoA<9, K> = make_layout(0, 10);
         let mut result = vec![Cluster::splat(0.0); 10];
         assert_eq!(
-            compute_aos8(&aos8, &mut result),
-            compute_soa8(&soa8, &mut result)
-        );
-        assert_eq!(
-            compute_aos8(&aos8, &mut result),
-            compute_aos_blob8(&aosblob8, &mut result)
+            compute(&aos, &dense::<9>(), &mut result),
+            compute(&aosoa, &dense::<9>(), &mut result)
         );
     }
 
-    // ------------------------------------------------------------------------
-    struct S9 {
-        a: Cluster,
-        b: Cluster,
-        c: Cluster,
-        d: Cluster,
-        e: Cluster,
-        f: Cluster,
-        g: Cluster,
-        h: Cluster,
-        i: Cluster,
-    }
-
-    fn make_aos9(seed: usize, n: usize) -> Vec<S9> {
-        let mut v = Vec::with_capacity(n);
-        for i in seed..seed + n {
-            v.push(S9 {
-                a: Cluster::splat(i as f32),
-                b: Cluster::splat((i / 3) as f32),
-                c: Cluster::splat((i * 2) as f32),
-                d: Cluster::splat((i * 3) as f32),
-                e: Cluster::splat((i * 4) as f32),
-                f: Cluster::splat((i + 5) as f32),
-                g: Cluster::splat((i * 6) as f32),
-                h: Cluster::splat((i * 7) as f32),
-                i: Cluster::splat((i * 8) as f32),
-            });
-        }
-        v
-    }
-
-    fn compute_aos9(data_set: &Vec<S9>, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        for (s9, r) in data_set.iter().zip(result.iter_mut()) {
-            let tmp =
-                s9.a.mul_add(s9.b, s9.c)
-                    .mul_add(s9.d, s9.e)
-                    .mul_add(s9.f, s9.g)
-                    .mul_add(s9.h, s9.i);
-            *r = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_aos9_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos9(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos9(data_set, result));
-        });
-    }
-
-    #[bench]
-
-    fn bench_9small_aos(b: &mut Bencher) {
-        bench_aos9_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_9big_aos(b: &mut Bencher) {
-        bench_aos9_impl(b, N_BIG);
-    }
-
-    // ------------------------------------------------------------------------
-    struct SoA9 {
-        a: Vec<Cluster>,
-        b: Vec<Cluster>,
-        c: Vec<Cluster>,
-        d: Vec<Cluster>,
-        e: Vec<Cluster>,
-        f: Vec<Cluster>,
-        g: Vec<Cluster>,
-        h: Vec<Cluster>,
-        i: Vec<Cluster>,
-    }
-
-    fn make_soa9(seed: usize, n: usize) -> SoA9 {
-        let mut c = Vec::with_capacity(n);
-        let mut b = Vec::with_capacity(n);
-        let mut e = Vec::with_capacity(n);
-        let mut d = Vec::with_capacity(n);
-        let mut a = Vec::with_capacity(n);
-        let mut f = Vec::with_capacity(n);
-        let mut i = Vec::with_capacity(n);
-        let mut h = Vec::with_capacity(n);
-        let mut g = Vec::with_capacity(n);
-        for j in seed..seed + n {
-            a.push(Cluster::splat(j as f32));
-            b.push(Cluster::splat((j / 3) as f32));
-            c.push(Cluster::splat((j * 2) as f32));
-            d.push(Cluster::splat((j * 3) as f32));
-            e.push(Cluster::splat((j * 4) as f32));
-            f.push(Cluster::splat((j + 5) as f32));
-            g.push(Cluster::splat((j * 6) as f32));
-            h.push(Cluster::splat((j * 7) as f32));
-            i.push(Cluster::splat((j * 8) as f32));
-        }
-        SoA9 {
-            a,
-            b,
-            c,
-            d,
-            e,
-            f,
-            g,
-            h,
-            i,
-        }
-    }
-
-    fn bench_soa9_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa9(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_soa9(data_set, result));
-        });
-    }
-
-    fn compute_soa9(data_set: &SoA9, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i]
-                .mul_add(data_set.b[i], data_set.c[i])
-                .mul_add(data_set.d[i], data_set.e[i])
-                .mul_add(data_set.f[i], data_set.g[i])
-                .mul_add(data_set.h[i], data_set.i[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
+    #[test]
+    fn test_9_aosoa_benchmarks() {
+        test_aosoa::<1>();
+        test_aosoa::<2>();
+        test_aosoa::<4>();
+        test_aosoa::<8>();
+        test_aosoa::<16>();
     }
 
-    #[bench]
-    fn bench_9small_soa(b: &mut Bencher) {
-        bench_soa9_impl(b, N_SMALL);
+    #[test]
+    fn test_12_benchmarks() {
+        test_layouts::<12, 12>(&dense());
     }
 
-    #[bench]
-    fn bench_9big_soa(b: &mut Bencher) {
-        bench_soa9_impl(b, N_BIG);
+    #[test]
+    fn test_16_benchmarks() {
+        test_layouts::<16, 16>(&dense());
     }
 
     // ------------------------------------------------------------------------
-    struct AoSblob9 {
-        data: Vec<Cluster>,
-    }
-
-    fn make_aos9_blob(seed: usize, n: usize) -> AoSblob9 {
-        let mut data = Vec::with_capacity(n * 9);
-        for i in seed..seed + n {
-            data.push(Cluster::splat(i as f32));
-            data.push(Cluster::splat((i / 3) as f32));
-            data.push(Cluster::splat((i * 2) as f32));
-            data.push(Cluster::splat((i * 3) as f32));
-            data.push(Cluster::splat((i * 4) as f32));
-            data.push(Cluster::splat((i + 5) as f32));
-            data.push(Cluster::splat((i * 6) as f32));
-            data.push(Cluster::splat((i * 7) as f32));
-            data.push(Cluster::splat((i * 8) as f32));
-        }
-        AoSblob9 { data }
-    }
 
-    fn compute_aos_blob9(data_set: &AoSblob9, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let mut i = 0;
-        for r in result.iter_mut() {
-            let tmp = data_set.data[i]
-                .mul_add(data_set.data[i + 1], data_set.data[i + 2])
-                .mul_add(data_set.data[i + 3], data_set.data[i + 4])
-                .mul_add(data_set.data[i + 5], data_set.data[i + 6])
-                .mul_add(data_set.data[i + 7], data_set.data[i + 8]);
-            *r = tmp;
-            sum += tmp;
-            i += 9;
-        }
-        sum
-    }
-
-    fn bench_aos_blob9_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos9_blob(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_aos_blob9(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_9small_blob_aos(b: &mut Bencher) {
-        bench_aos_blob9_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_9big_blob_aos(b: &mut Bencher) {
-        bench_aos_blob9_impl(b, N_BIG);
+    fn sweep_dense<L: Layout<F>, const F: usize>(config: &SweepConfig, out: &mut Vec<Series>) {
+        let name = format!("{F}_{}", L::NAME);
+        out.extend(sweep_layout::<L, F, F>(&name, config, &dense()).unwrap());
     }
 
+    /// Working-set sweep of every layout benchmark above. Takes long, run with
+    /// `cargo test --release -- --ignored --nocapture sweep_layouts`, with
+    /// `CACHE_BENCH_OUT` set to also export every point.
     #[test]
-    fn test_9_benchmarks() {
-        let aos9 = make_aos9(0, 10);
-        let soa9 = make_soa9(0, 10);
-        let aosblob9 = make_aos9_blob(0, 10);
-        let mut result = vec![Cluster::splat(0.0); 10];
-        let tmp = compute_aos9(&aos9, &mut result);
-        assert_eq!(tmp, compute_soa9(&soa9, &mut result));
-        assert_eq!(tmp, compute_aos_blob9(&aosblob9, &mut result));
-    }
-    // ------------------------------------------------------------------------
-
-    fn compute_sparse_aos_blob9(data_set: &AoSblob9, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let mut i = 0;
-        for r in result.iter_mut() {
-            let tmp = data_set.data[i]
-                .mul(data_set.data[i + 2])
-                .mul_add(data_set.data[i + 5], data_set.data[i + 6])
-                .mul_add(data_set.data[i + 7], data_set.data[i + 8]);
-            *r = tmp;
-            sum += tmp;
-            i += 9;
-        }
-        sum
-    }
-
-    fn bench_sparse_aos_blob9_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_aos9_blob(i, n));
-        let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_sparse_aos_blob9(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_sparse_9small_blob_aos(b: &mut Bencher) {
-        bench_sparse_aos_blob9_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_sparse_9big_blob_aos(b: &mut Bencher) {
-        bench_sparse_aos_blob9_impl(b, N_BIG);
+    #[ignore]
+    fn sweep_layouts() {
+        let config = SweepConfig::for_topology(&CacheTopology::detect_or_fallback());
+        let mut out = Vec::new();
+        sweep_dense::<AoS<3>, 3>(&config, &mut out);
+        sweep_dense::<SoA<3>, 3>(&config, &mut out);
+        sweep_dense::<AoS<4>, 4>(&config, &mut out);
+        sweep_dense::<SoA<4>, 4>(&config, &mut out);
+        sweep_dense::<Blob<4>, 4>(&config, &mut out);
+        sweep_dense::<AoS<5>, 5>(&config, &mut out);
+        sweep_dense::<SoA<5>, 5>(&config, &mut out);
+        sweep_dense::<Blob<5>, 5>(&config, &mut out);
+        sweep_dense::<AoS<7>, 7>(&config, &mut out);
+        sweep_dense::<SoA<7>, 7>(&config, &mut out);
+        out.extend(sweep_layout::<AoS<7>, 7, 4>("sparse_7_aos", &config, &SPARSE_7).unwrap());
+        out.extend(sweep_layout::<SoA<7>, 7, 4>("sparse_7_soa", &config, &SPARSE_7).unwrap());
+        sweep_dense::<AoS<8>, 8>(&config, &mut out);
+        sweep_dense::<SoA<8>, 8>(&config, &mut out);
+        sweep_dense::<Blob<8>, 8>(&config, &mut out);
+        sweep_dense::<AoS<9>, 9>(&config, &mut out);
+        sweep_dense::<SoA<9>, 9>(&config, &mut out);
+        sweep_dense::<Blob<9>, 9>(&config, &mut out);
+        sweep_dense::<AoSoA<9, 1>, 9>(&config, &mut out);
+        sweep_dense::<AoSoA<9, 2>, 9>(&config, &mut out);
+        sweep_dense::<AoSoA<9, 4>, 9>(&config, &mut out);
+        sweep_dense::<AoSoA<9, 8>, 9>(&config, &mut out);
+        sweep_dense::<AoSoA<9, 16>, 9>(&config, &mut out);
+        out.extend(sweep_layout::<Blob<9>, 9, 6>("sparse_9_blob", &config, &SPARSE_9).unwrap());
+        out.extend(sweep_layout::<SoA<9>, 9, 6>("sparse_9_soa", &config, &SPARSE_9).unwrap());
+        sweep_dense::<AoS<12>, 12>(&config, &mut out);
+        sweep_dense::<SoA<12>, 12>(&config, &mut out);
+        sweep_dense::<Blob<12>, 12>(&config, &mut out);
+        sweep_dense::<AoS<16>, 16>(&config, &mut out);
+        sweep_dense::<SoA<16>, 16>(&config, &mut out);
+        sweep_dense::<Blob<16>, 16>(&config, &mut out);
+        for series in out {
+            println!("{series}");
+        }
+    }
+
+    fn simulate_dense<L: Layout<F>, const F: usize>(topology: &CacheTopology) {
+        let mut hierarchy = Hierarchy::from_topology(topology, Replacement::Lru);
+        let rotation = harness::Rotation::default();
+        let sim = simulate_layout::<L, F, F>(&mut hierarchy, &dense(), 256, M, &rotation);
+        let misses: Vec<String> = (0..sim.levels.len())
+            .map(|level| format!("L{} {:>8.1}", level + 1, sim.misses_per_iteration(level)))
+            .collect();
+        println!("{:<10} {}", format!("{F}_{}", L::NAME), misses.join("  "));
+    }
+
+    /// Simulated misses per iteration of the big dense benchmarks in the
+    /// detected caches. Run with
+    /// `cargo test --release -- --ignored --nocapture simulate_layouts`.
+    #[test]
+    #[ignore]
+    fn simulate_layouts() {
+        let topology = CacheTopology::detect_or_fallback();
+        simulate_dense::<AoS<3>, 3>(&topology);
+        simulate_dense::<SoA<3>, 3>(&topology);
+        simulate_dense::<AoS<7>, 7>(&topology);
+        simulate_dense::<SoA<7>, 7>(&topology);
+        simulate_dense::<AoS<9>, 9>(&topology);
+        simulate_dense::<SoA<9>, 9>(&topology);
+        simulate_dense::<Blob<9>, 9>(&topology);
+        simulate_dense::<AoSoA<9, 4>, 9>(&topology);
+        simulate_dense::<AoS<16>, 16>(&topology);
+        simulate_dense::<SoA<16>, 16>(&topology);
+        simulate_dense::<Blob<16>, 16>(&topology);
     }
 
     // ------------------------------------------------------------------------
 
-    fn compute_sparse_soa9(data_set: &SoA9, result: &mut [Cluster]) -> Cluster {
-        let mut sum = Cluster::splat(0.0);
-        let n = data_set.a.len();
-        for i in 0..n {
-            let tmp = data_set.a[i]
-                .mul(data_set.c[i])
-                .mul_add(data_set.f[i], data_set.g[i])
-                .mul_add(data_set.h[i], data_set.i[i]);
-            result[i] = tmp;
-            sum += tmp;
-        }
-        sum
-    }
-
-    fn bench_sparse_soa9_impl(b: &mut Bencher, n: usize) {
-        let data_sets = make_datasets(|i| make_soa9(i, n));
+    fn measure_dense<L: Layout<F>, const F: usize>(n: usize) -> Summary {
+        let data_sets = make_datasets(|i| make_layout::<L, F>(i, n));
         let mut result = vec![Cluster::splat(0.0); n];
-        bench(b, &data_sets, &mut result, |data_set, result| {
-            black_box(compute_sparse_soa9(data_set, result));
-        });
-    }
-
-    #[bench]
-    fn bench_sparse_9small_soa(b: &mut Bencher) {
-        bench_sparse_soa9_impl(b, N_SMALL);
-    }
-
-    #[bench]
-    fn bench_sparse_9big_soa(b: &mut Bencher) {
-        bench_sparse_soa9_impl(b, N_BIG);
+        let fields = dense::<F>();
+        let config = harness::Config::default();
+        let m = harness::run(
+            &config,
+            harness::rotate(&data_sets, &config.rotation, |data_set| {
+                compute(data_set, &fields, &mut result)
+            }),
+        );
+        m.summary()
     }
 
+    /// Median of every layout benchmark hot, warm and cold side by side. Run
+    /// with `cargo test --release -- --ignored --nocapture compare_cache_modes`.
     #[test]
-    fn test_sparse_9_benchmarks() {
-        let aos9 = make_aos9_blob(0, 10);
-        let soa9 = make_soa9(0, 10);
-        let mut result = vec![Cluster::splat(0.0); 10];
-        assert_eq!(
-            compute_sparse_aos_blob9(&aos9, &mut result),
-            compute_sparse_soa9(&soa9, &mut result)
-        );
+    #[ignore]
+    fn compare_cache_modes() {
+        let options = Options {
+            filter: Filter {
+                modules: vec!["layout".to_string()],
+                ..Default::default()
+            },
+            caches: vec![
+                CacheMode::Hot,
+                CacheMode::Warm,
+                CacheMode::Cold(Eviction::default()),
+            ],
+            ..Default::default()
+        };
+        cli::run(&options).unwrap();
+    }
+
+    /// AoS against SoA with 7 fields, only reporting a difference if the
+    /// confidence intervals of the medians are disjoint. Run with
+    /// `cargo test --release -- --ignored --nocapture compare_7big`.
+    #[test]
+    #[ignore]
+    fn compare_7big() {
+        let aos = measure_dense::<AoS<7>, 7>(256);
+        let soa = measure_dense::<SoA<7>, 7>(256);
+        println!("aos: {aos}");
+        println!("soa: {soa}");
+        println!("soa vs aos: {}", compare(&soa, &aos));
     }
 }
@@ -0,0 +1,5 @@
+use std::{env, process::ExitCode};
+
+fn main() -> ExitCode {
+    cache_bench::cli::main(env::args().skip(1))
+}
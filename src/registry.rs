@@ -0,0 +1,604 @@
+//! Every benchmark as data: its [`Meta`] and a closure measuring it, so that
+//! runners can list, filter and run them without knowing the types involved.
+//!
+//! Every experiment can also run in trace mode, recording the loads and
+//! stores of one period of its rotation instead of timing it.
+//!
+//! The layout benchmarks are registered here, the kernels of the other
+//! modules by their own `experiments()`.
+
+use std::rc::Rc;
+
+use crate::{
+    aligned::Alignment,
+    bench_pointer_arithmetic, bench_runtime, bench_stride,
+    cache::{footprint, Footprint},
+    columns::{self, compute_rows, Rows},
+    harness::{self, Config, Measurement},
+    latency,
+    layout::{
+        compute, dense, make_layout, AoS, AoSoA, Blob, Layout, PlacedSoA, Realigned, Slots, SoA,
+        SPARSE_7, SPARSE_9,
+    },
+    report::{Meta, Record},
+    rng,
+    topology::{CacheTopology, Target},
+    trace::Access,
+    utils::{make_data, make_datasets, make_n_datasets, Cluster, Data, M},
+};
+
+/// elements per dataset of the small benchmarks
+pub const N_SMALL: usize = 9;
+
+/// elements per dataset of the big benchmarks
+pub const N_BIG: usize = 256;
+
+pub struct Experiment {
+    pub meta: Meta,
+    /// builds the datasets and measures the kernel
+    pub run: Box<dyn Fn(&Config) -> Measurement>,
+    /// builds the datasets and records the accesses of the kernel over one
+    /// period of the rotation, empty without the `trace` feature
+    pub trace: Box<Tracer>,
+}
+
+/// records the accesses of an experiment
+pub type Tracer = dyn Fn(&Config) -> Vec<Access>;
+
+impl Experiment {
+    /// runs the experiment with `config` and records it with its rotation
+    /// and cache mode
+    pub fn measure(&self, config: &Config) -> Record {
+        let measurement = (self.run)(config);
+        let meta = Meta {
+            rotation: config.effective_rotation().to_string(),
+            cache: config.cache.to_string(),
+            ..self.meta.clone()
+        };
+        Record::new(meta, &measurement)
+    }
+
+    /// [`compute`] over `fields` of `L`, rotating over `meta.m` datasets of
+    /// `meta.n` elements in the cache mode of the config
+    pub fn layout<L: Layout<F>, const F: usize, const K: usize>(
+        meta: Meta,
+        fields: [usize; K],
+    ) -> Self {
+        let (n, m) = (meta.n, meta.m);
+        Self::layout_with(meta, fields, move || {
+            make_n_datasets(m, |i| make_layout::<L, F>(i, n))
+        })
+    }
+
+    /// like [`Experiment::layout`] over the datasets built by `make`
+    pub fn layout_with<L: Layout<F>, const F: usize, const K: usize>(
+        meta: Meta,
+        fields: [usize; K],
+        make: impl Fn() -> Vec<L> + Clone + 'static,
+    ) -> Self {
+        let n = meta.n;
+        let trace_make = make.clone();
+        Experiment {
+            meta,
+            run: Box::new(move |config| {
+                let data_sets = make();
+                let mut result = vec![Cluster::splat(0.0); n];
+                let output = footprint(&result);
+                harness::run_on(config, &data_sets, &output, |data_set| {
+                    compute(data_set, &fields, &mut result)
+                })
+            }),
+            trace: Box::new(move |config| {
+                let data_sets = trace_make();
+                let mut result = vec![Cluster::splat(0.0); n];
+                harness::trace_on(config, &data_sets, |data_set| {
+                    compute(data_set, &fields, &mut result)
+                })
+            }),
+        }
+    }
+
+    /// [`compute_rows`] with `f`, rotating over `meta.m` datasets of
+    /// `meta.n` rows built by `make_row`
+    pub fn rows<R: Rows + 'static>(
+        meta: Meta,
+        make_row: fn(usize) -> R::Row,
+        f: fn(R::Row) -> Cluster,
+    ) -> Self {
+        let (n, m) = (meta.n, meta.m);
+        let make = move || make_n_datasets(m, |i| R::make(i, n, make_row));
+        Experiment {
+            meta,
+            run: Box::new(move |config| {
+                let data_sets = make();
+                let mut result = vec![Cluster::splat(0.0); n];
+                let output = footprint(&result);
+                harness::run_on(config, &data_sets, &output, |data_set| {
+                    compute_rows(data_set, &mut result, f)
+                })
+            }),
+            trace: Box::new(move |config| {
+                let data_sets = make();
+                let mut result = vec![Cluster::splat(0.0); n];
+                harness::trace_on(config, &data_sets, |data_set| {
+                    compute_rows(data_set, &mut result, f)
+                })
+            }),
+        }
+    }
+
+    /// A kernel over pairs of input and output [`Data`], rotating over [`M`]
+    /// of each. `kernel` builds the closure measured, which also gets all
+    /// input datasets.
+    pub fn data<K>(meta: Meta, kernel: impl Fn() -> K + 'static) -> Self
+    where
+        K: FnMut(&[Data], &Data, &mut Data) -> f32,
+    {
+        Self::data_with(meta, make_data, make_data, kernel)
+    }
+
+    /// Like [`Experiment::data`] over input datasets built by `make_in` and
+    /// output datasets built by `make_out`.
+    pub fn data_with<D: Footprint + 'static, K>(
+        meta: Meta,
+        make_in: impl Fn() -> D + 'static,
+        make_out: impl Fn() -> D + 'static,
+        kernel: impl Fn() -> K + 'static,
+    ) -> Self
+    where
+        K: FnMut(&[D], &D, &mut D) -> f32,
+    {
+        let make = Rc::new(move || (make_datasets(|_| make_in()), make_datasets(|_| make_out())));
+        let trace_make = Rc::clone(&make);
+        let kernel = Rc::new(kernel);
+        let trace_kernel = Rc::clone(&kernel);
+        Experiment {
+            meta,
+            run: Box::new(move |config| {
+                let (data_sets_in, mut data_sets_out) = make();
+                let mut f = kernel();
+                harness::run_on_pair(config, &data_sets_in, &mut data_sets_out, |d_in, d_out| {
+                    f(&data_sets_in, d_in, d_out)
+                })
+            }),
+            trace: Box::new(move |config| {
+                let (data_sets_in, mut data_sets_out) = trace_make();
+                let mut f = trace_kernel();
+                harness::trace_on_pair(config, &data_sets_in, &mut data_sets_out, |d_in, d_out| {
+                    f(&data_sets_in, d_in, d_out)
+                })
+            }),
+        }
+    }
+}
+
+/// `small` or `big` for the two standard sizes
+fn size_tag(n: usize) -> &'static str {
+    if n == N_SMALL {
+        "small"
+    } else {
+        "big"
+    }
+}
+
+/// [`Experiment::layout`] with the allocations of `L` placed by
+/// `alignment`, named with it unless it is [`Alignment::Vec`]
+fn aligned<L: Slots<F> + 'static, const F: usize, const K: usize>(
+    meta: Meta,
+    fields: [usize; K],
+    alignment: Alignment,
+) -> Experiment {
+    let named = |meta: Meta| meta.with_variant(alignment.name()).with_tag("align");
+    match alignment {
+        Alignment::Vec => Experiment::layout::<L, F, K>(meta, fields),
+        Alignment::Line => Experiment::layout::<Realigned<L, F, 64, 0>, F, K>(named(meta), fields),
+        Alignment::Page => {
+            Experiment::layout::<Realigned<L, F, 4096, 0>, F, K>(named(meta), fields)
+        }
+        Alignment::Misaligned4 => {
+            Experiment::layout::<Realigned<L, F, 64, 4>, F, K>(named(meta), fields)
+        }
+        Alignment::Misaligned16 => {
+            Experiment::layout::<Realigned<L, F, 64, 16>, F, K>(named(meta), fields)
+        }
+        Alignment::Misaligned32 => {
+            Experiment::layout::<Realigned<L, F, 64, 32>, F, K>(named(meta), fields)
+        }
+    }
+}
+
+/// the dense benchmark of `L` at both sizes
+fn dense_sizes<L: Slots<F> + 'static, const F: usize>(
+    out: &mut Vec<Experiment>,
+    alignment: Alignment,
+) {
+    for n in [N_SMALL, N_BIG] {
+        out.push(aligned::<L, F, F>(
+            Meta::layout::<L, F>(&dense::<F>(), n, M).with_tag(size_tag(n)),
+            dense(),
+            alignment,
+        ));
+    }
+}
+
+fn sparse_sizes<L: Slots<F> + 'static, const F: usize, const K: usize>(
+    out: &mut Vec<Experiment>,
+    fields: [usize; K],
+    alignment: Alignment,
+) {
+    for n in [N_SMALL, N_BIG] {
+        out.push(aligned::<L, F, K>(
+            Meta::layout::<L, F>(&fields, n, M).with_tag(size_tag(n)),
+            fields,
+            alignment,
+        ));
+    }
+}
+
+fn aosoa_sizes<const K: usize>(out: &mut Vec<Experiment>, alignment: Alignment) {
+    for n in [N_SMALL, N_BIG] {
+        let meta = Meta::layout::<AoSoA<9, K>, 9>(&dense::<9>(), n, M)
+            .with_variant(&K.to_string())
+            .with_tag(size_tag(n));
+        out.push(aligned::<AoSoA<9, K>, 9, 9>(meta, dense(), alignment));
+    }
+}
+
+/// `SoA<9>` dense and sparse at both sizes, with the columns of all datasets
+/// placed in one buffer by [`PlacedSoA::datasets`]
+fn placed_sizes<const ALIGN: usize>(out: &mut Vec<Experiment>, placement: &str) {
+    for n in [N_SMALL, N_BIG] {
+        let meta = |touched: &[usize]| {
+            Meta::layout::<PlacedSoA<9, ALIGN>, 9>(touched, n, M)
+                .with_variant(placement)
+                .with_tag("placement")
+                .with_tag(size_tag(n))
+        };
+        // seeded by the name, so that the placement does not depend on what
+        // else was built before
+        let make = |meta: &Meta| {
+            let seed = rng::hash(&meta.name);
+            move || PlacedSoA::<9, ALIGN>::datasets(M, n, seed)
+        };
+        let dense_meta = meta(&dense::<9>());
+        let make_dense = make(&dense_meta);
+        out.push(Experiment::layout_with(
+            dense_meta,
+            dense::<9>(),
+            make_dense,
+        ));
+        let sparse_meta = meta(&SPARSE_9);
+        let make_sparse = make(&sparse_meta);
+        out.push(Experiment::layout_with(sparse_meta, SPARSE_9, make_sparse));
+    }
+}
+
+/// dense with `n <= N_BIG` and as many datasets as needed for the rotation to
+/// run out of `target`
+fn target<L: Slots<F> + 'static, const F: usize>(
+    out: &mut Vec<Experiment>,
+    topology: &CacheTopology,
+    target: Target,
+    alignment: Alignment,
+) {
+    let (n, m) = topology.size_for(target, N_BIG, F * size_of::<Cluster>());
+    let meta = Meta::layout::<L, F>(&dense::<F>(), n, m)
+        .with_variant(&target.to_string())
+        .with_tag(&target.to_string());
+    out.push(aligned::<L, F, F>(meta, dense(), alignment));
+}
+
+/// Every registered benchmark, with the cache-level targets sized for
+/// `topology`.
+pub fn experiments(topology: &CacheTopology) -> Vec<Experiment> {
+    experiments_aligned(topology, &[Alignment::Vec])
+}
+
+/// Like [`experiments`], with the layout benchmarks registered once per
+/// alignment of `alignments`.
+pub fn experiments_aligned(topology: &CacheTopology, alignments: &[Alignment]) -> Vec<Experiment> {
+    let mut out: Vec<Experiment> = alignments
+        .iter()
+        .flat_map(|&alignment| layout_experiments(topology, alignment))
+        .collect();
+    out.extend(columns::experiments());
+    out.extend(bench_runtime::experiments());
+    out.extend(bench_pointer_arithmetic::experiments());
+    out.extend(bench_stride::experiments());
+    out.extend(latency::experiments(topology));
+    out
+}
+
+/// the layout benchmarks with their allocations placed by `alignment`, the
+/// placed `SoA<9>` only with [`Alignment::Vec`], as it places its own
+fn layout_experiments(topology: &CacheTopology, alignment: Alignment) -> Vec<Experiment> {
+    let mut out = Vec::new();
+    dense_sizes::<AoS<3>, 3>(&mut out, alignment);
+    dense_sizes::<SoA<3>, 3>(&mut out, alignment);
+
+    dense_sizes::<AoS<4>, 4>(&mut out, alignment);
+    dense_sizes::<SoA<4>, 4>(&mut out, alignment);
+    dense_sizes::<Blob<4>, 4>(&mut out, alignment);
+
+    dense_sizes::<AoS<5>, 5>(&mut out, alignment);
+    dense_sizes::<SoA<5>, 5>(&mut out, alignment);
+    dense_sizes::<Blob<5>, 5>(&mut out, alignment);
+
+    dense_sizes::<AoS<7>, 7>(&mut out, alignment);
+    dense_sizes::<SoA<7>, 7>(&mut out, alignment);
+    sparse_sizes::<AoS<7>, 7, 4>(&mut out, SPARSE_7, alignment);
+    sparse_sizes::<SoA<7>, 7, 4>(&mut out, SPARSE_7, alignment);
+
+    dense_sizes::<AoS<8>, 8>(&mut out, alignment);
+    dense_sizes::<SoA<8>, 8>(&mut out, alignment);
+    dense_sizes::<Blob<8>, 8>(&mut out, alignment);
+
+    dense_sizes::<AoS<9>, 9>(&mut out, alignment);
+    dense_sizes::<SoA<9>, 9>(&mut out, alignment);
+    dense_sizes::<Blob<9>, 9>(&mut out, alignment);
+    sparse_sizes::<Blob<9>, 9, 6>(&mut out, SPARSE_9, alignment);
+    sparse_sizes::<SoA<9>, 9, 6>(&mut out, SPARSE_9, alignment);
+    aosoa_sizes::<1>(&mut out, alignment);
+    aosoa_sizes::<2>(&mut out, alignment);
+    aosoa_sizes::<4>(&mut out, alignment);
+    aosoa_sizes::<8>(&mut out, alignment);
+    aosoa_sizes::<16>(&mut out, alignment);
+    let targets = [
+        Target::Level(1),
+        Target::Level(2),
+        Target::Level(3),
+        Target::Memory,
+    ];
+    for t in targets {
+        target::<AoS<9>, 9>(&mut out, topology, t, alignment);
+    }
+    for t in targets {
+        target::<SoA<9>, 9>(&mut out, topology, t, alignment);
+    }
+    if alignment == Alignment::Vec {
+        placed_sizes::<4096>(&mut out, "4k");
+        placed_sizes::<{ 1 << 20 }>(&mut out, "1m");
+        placed_sizes::<0>(&mut out, "random");
+    }
+
+    dense_sizes::<AoS<12>, 12>(&mut out, alignment);
+    dense_sizes::<SoA<12>, 12>(&mut out, alignment);
+    dense_sizes::<Blob<12>, 12>(&mut out, alignment);
+
+    dense_sizes::<AoS<16>, 16>(&mut out, alignment);
+    dense_sizes::<SoA<16>, 16>(&mut out, alignment);
+    dense_sizes::<Blob<16>, 16>(&mut out, alignment);
+    out
+}
+
+// ----------------------------------------------------------------------------
+
+/// `--size` values: an element count, or a cache-level target
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    N(usize),
+    Target(Target),
+}
+
+impl Size {
+    /// `small`, `big`, `l1`, `l2`, ..., `mem` or an element count
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "small" => Some(Size::N(N_SMALL)),
+            "big" => Some(Size::N(N_BIG)),
+            "mem" => Some(Size::Target(Target::Memory)),
+            _ => match s.strip_prefix('l') {
+                Some(level) => level.parse().ok().map(|l| Size::Target(Target::Level(l))),
+                None => s.parse().ok().map(Size::N),
+            },
+        }
+    }
+
+    fn matches(&self, meta: &Meta) -> bool {
+        match self {
+            Size::N(n) => meta.n == *n,
+            Size::Target(target) => meta.has_tag(&target.to_string()),
+        }
+    }
+}
+
+/// Selects experiments by their metadata. Empty lists match everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub layouts: Vec<String>,
+    pub fields: Vec<usize>,
+    /// only benchmarks touching a subset of the fields
+    pub sparse: bool,
+    pub sizes: Vec<Size>,
+    pub datasets: Vec<usize>,
+    pub modules: Vec<String>,
+    /// tags that all have to be present
+    pub tags: Vec<String>,
+    /// substrings of the name, any of which has to match
+    pub names: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, meta: &Meta) -> bool {
+        (self.layouts.is_empty() || self.layouts.contains(&meta.layout))
+            && (self.fields.is_empty() || self.fields.contains(&meta.fields))
+            && (!self.sparse || meta.is_sparse())
+            && (self.sizes.is_empty() || self.sizes.iter().any(|s| s.matches(meta)))
+            && (self.datasets.is_empty() || self.datasets.contains(&meta.m))
+            && (self.modules.is_empty() || self.modules.contains(&meta.module))
+            && self.tags.iter().all(|t| meta.has_tag(t))
+            && (self.names.is_empty() || self.names.iter().any(|n| meta.name.contains(n)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(filter: &Filter) -> Vec<String> {
+        experiments(&CacheTopology::fallback())
+            .into_iter()
+            .filter(|e| filter.matches(&e.meta))
+            .map(|e| format!("{}/{}", e.meta.name, e.meta.n))
+            .collect()
+    }
+
+    #[test]
+    fn test_experiments() {
+        let experiments = experiments(&CacheTopology::fallback());
+        assert_eq!(experiments.len(), 300);
+        for (i, e) in experiments.iter().enumerate() {
+            assert!(
+                experiments[..i]
+                    .iter()
+                    .all(|o| (&o.meta.name, o.meta.n) != (&e.meta.name, e.meta.n)),
+                "{} registered twice",
+                e.meta.name
+            );
+        }
+        for module in [
+            "layout",
+            "columns",
+            "bench_runtime",
+            "bench_pointer_arithmetic",
+            "bench_stride",
+            "latency",
+        ] {
+            let e = experiments
+                .iter()
+                .find(|e| e.meta.module == module)
+                .unwrap();
+            let m = (e.run)(&Config::quick());
+            assert_eq!(m.samples.len(), Config::quick().samples);
+            let trace = (e.trace)(&Config::quick());
+            assert_eq!(
+                trace.is_empty(),
+                !crate::trace::enabled(),
+                "{}",
+                e.meta.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_experiments_aligned() {
+        let topology = CacheTopology::fallback();
+        let layouts = |e: &&Experiment| e.meta.module == "layout";
+        let vec = experiments(&topology);
+        let aligned = experiments_aligned(&topology, &[Alignment::Misaligned4]);
+        // all but the placed SoA9
+        assert_eq!(
+            aligned.iter().filter(layouts).count(),
+            vec.iter().filter(layouts).count() - 12
+        );
+        assert_eq!(aligned.len(), vec.len() - 12);
+        let e = &aligned[0];
+        assert_eq!(e.meta.name, "3_aos_off4");
+        assert!(e.meta.has_tag("align"));
+        let m = (e.run)(&Config::quick());
+        assert_eq!(m.samples.len(), Config::quick().samples);
+        let trace = (e.trace)(&Config::quick());
+        if crate::trace::enabled() {
+            assert!(trace.iter().any(|a| a.addr % 64 == 4));
+        }
+        assert_eq!(
+            experiments_aligned(&topology, &Alignment::ALL).len(),
+            vec.len() + 5 * (vec.iter().filter(layouts).count() - 12)
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter {
+            layouts: vec!["soa".to_string()],
+            fields: vec![9],
+            sizes: vec![Size::N(N_BIG)],
+            ..Default::default()
+        };
+        assert_eq!(
+            select(&filter),
+            [
+                "9_soa/256",
+                "sparse_9_soa/256",
+                "9_soa_l2/256",
+                "9_soa_l3/256",
+                "9_soa_mem/256",
+                "9_soa_4k/256",
+                "sparse_9_soa_4k/256",
+                "9_soa_1m/256",
+                "sparse_9_soa_1m/256",
+                "9_soa_random/256",
+                "sparse_9_soa_random/256"
+            ]
+        );
+        let filter = Filter {
+            datasets: vec![M],
+            tags: vec!["dense".to_string()],
+            ..filter
+        };
+        assert_eq!(
+            select(&filter),
+            [
+                "9_soa/256",
+                "9_soa_4k/256",
+                "9_soa_1m/256",
+                "9_soa_random/256"
+            ]
+        );
+        let filter = Filter {
+            tags: vec!["placement".to_string(), "small".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(select(&filter).len(), 6);
+
+        let filter = Filter {
+            sparse: true,
+            sizes: vec![Size::parse("small").unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(
+            select(&filter),
+            [
+                "sparse_7_aos/9",
+                "sparse_7_soa/9",
+                "sparse_9_blob/9",
+                "sparse_9_soa/9",
+                "sparse_9_soa_4k/9",
+                "sparse_9_soa_1m/9",
+                "sparse_9_soa_random/9"
+            ]
+        );
+
+        let filter = Filter {
+            sizes: vec![Size::parse("l1").unwrap()],
+            layouts: vec!["aos".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(select(&filter), ["9_aos_l1/56"]);
+
+        let filter = Filter {
+            names: vec!["aosoa_16".to_string()],
+            datasets: vec![M],
+            ..Default::default()
+        };
+        assert_eq!(select(&filter), ["9_aosoa_16/9", "9_aosoa_16/256"]);
+
+        let filter = Filter {
+            tags: vec!["big".to_string(), "sparse".to_string()],
+            fields: vec![7],
+            ..Default::default()
+        };
+        assert_eq!(select(&filter), ["sparse_7_aos/256", "sparse_7_soa/256"]);
+
+        let filter = Filter {
+            modules: vec!["bench_pointer_arithmetic".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            select(&filter),
+            ["index_slices/256", "index_ptr_arithmetics/256"]
+        );
+        assert_eq!(Size::parse("mem"), Some(Size::Target(Target::Memory)));
+        assert_eq!(Size::parse("x"), None);
+    }
+}
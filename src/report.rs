@@ -0,0 +1,640 @@
+//! Structured benchmark records, exported as JSON Lines and CSV.
+//!
+//! Set `CACHE_BENCH_OUT` to a directory to have every benchmark append its
+//! record to `results.jsonl` and `results.csv` there.
+
+use std::{
+    borrow::Cow,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    cache::CacheMode,
+    clock,
+    harness::{Measurement, Rotation},
+    json::{self, Value},
+    layout::Layout,
+    roofline::Ops,
+    simd,
+    stats::Summary,
+    utils::Cluster,
+};
+
+/// environment variable naming the directory records are appended to
+pub const OUT_VAR: &str = "CACHE_BENCH_OUT";
+
+/// what was measured
+#[derive(Clone, Debug, PartialEq)]
+pub struct Meta {
+    pub name: String,
+    /// module defining the kernel, relative to the crate root
+    pub module: String,
+    /// labels to group benchmarks by, e.g. `dense`, `big`, `runtime`
+    pub tags: Vec<String>,
+    pub layout: String,
+    /// fields per element
+    pub fields: usize,
+    /// indices of the fields read by the kernel
+    pub touched: Vec<usize>,
+    /// elements per dataset
+    pub n: usize,
+    /// number of datasets
+    pub m: usize,
+    /// elements processed per iteration
+    pub elements: usize,
+    /// bytes read per element, 0 if not declared
+    pub bytes_read: usize,
+    /// bytes written per element
+    pub bytes_written: usize,
+    /// floating point operations per element, none if not declared
+    pub ops: Ops,
+    /// anything else distinguishing the benchmark, empty for the plain one
+    pub variant: String,
+    /// order the datasets were visited in, a [`Rotation`] as text
+    pub rotation: String,
+    /// cache state every iteration started in, a [`CacheMode`] as text
+    pub cache: String,
+    /// [`simd::BACKEND`] of the build that measured it
+    pub simd: String,
+}
+
+impl Meta {
+    /// The [`compute`](crate::layout::compute) kernel over `touched` of layout
+    /// `L`, named like the benchmarks: `9_soa`, `sparse_7_aos`. Tagged `dense`
+    /// or `sparse`. Reads the `touched` fields, chains them and writes one
+    /// `Cluster` per element.
+    pub fn layout<L: Layout<F>, const F: usize>(touched: &[usize], n: usize, m: usize) -> Self {
+        let sparse = touched.len() < F;
+        Meta {
+            name: format!("{}{F}_{}", if sparse { "sparse_" } else { "" }, L::NAME),
+            module: "layout".to_string(),
+            tags: vec![if sparse { "sparse" } else { "dense" }.to_string()],
+            layout: L::NAME.to_string(),
+            fields: F,
+            touched: touched.to_vec(),
+            n,
+            m,
+            elements: n,
+            bytes_read: touched.len() * size_of::<Cluster>(),
+            bytes_written: size_of::<Cluster>(),
+            ops: Ops::chain(touched.len()),
+            variant: String::new(),
+            rotation: Rotation::default().to_string(),
+            cache: CacheMode::default().to_string(),
+            simd: simd::BACKEND.to_string(),
+        }
+    }
+
+    /// any other kernel, reading `touched` of the `fields` of `layout`, one
+    /// `Cluster` each per element, and writing and computing nothing unless
+    /// declared with [`Meta::with_bytes`] and [`Meta::with_ops`]
+    pub fn kernel(
+        module: &str,
+        name: &str,
+        layout: &str,
+        fields: usize,
+        touched: &[usize],
+        n: usize,
+        m: usize,
+    ) -> Self {
+        Meta {
+            name: name.to_string(),
+            module: module.to_string(),
+            tags: Vec::new(),
+            layout: layout.to_string(),
+            fields,
+            touched: touched.to_vec(),
+            n,
+            m,
+            elements: n,
+            bytes_read: touched.len() * size_of::<Cluster>(),
+            bytes_written: 0,
+            ops: Ops::NONE,
+            variant: String::new(),
+            rotation: Rotation::default().to_string(),
+            cache: CacheMode::default().to_string(),
+            simd: simd::BACKEND.to_string(),
+        }
+    }
+
+    /// sets the variant and appends it to the name
+    pub fn with_variant(mut self, variant: &str) -> Self {
+        self.name = format!("{}_{variant}", self.name);
+        self.variant = variant.to_string();
+        self
+    }
+
+    /// declares the bytes read and written per element
+    pub fn with_bytes(mut self, read: usize, written: usize) -> Self {
+        self.bytes_read = read;
+        self.bytes_written = written;
+        self
+    }
+
+    /// declares the operations per element
+    pub fn with_ops(mut self, ops: Ops) -> Self {
+        self.ops = ops;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        self.touched.len() < self.fields
+    }
+
+    /// bytes read and written per iteration
+    pub fn bytes(&self) -> usize {
+        (self.bytes_read + self.bytes_written) * self.elements
+    }
+}
+
+/// one benchmark run: what was measured, the raw samples and their summary
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub meta: Meta,
+    /// iterations per sample
+    pub iters: u64,
+    /// time per iteration of every sample in ns
+    pub samples: Vec<f64>,
+    pub summary: Summary,
+    /// rate of the cycle counter, if the machine has one
+    pub cycles_per_ns: Option<f64>,
+    /// median count per iteration of every event counter read, by name
+    pub counters: Vec<(String, f64)>,
+}
+
+impl Record {
+    pub fn new(meta: Meta, measurement: &Measurement) -> Self {
+        Record {
+            meta,
+            iters: measurement.iters,
+            samples: measurement.samples.clone(),
+            summary: measurement.summary(),
+            cycles_per_ns: clock::cycles_per_ns(),
+            counters: measurement
+                .counters
+                .iter()
+                .map(|(c, count)| (c.name().to_string(), *count))
+                .collect(),
+        }
+    }
+
+    /// bytes read and written per second at the median, if declared
+    pub fn bytes_per_s(&self) -> Option<f64> {
+        let bytes = self.meta.bytes();
+        (bytes > 0).then(|| bytes as f64 / self.summary.median * 1e9)
+    }
+
+    pub fn elements_per_s(&self) -> Option<f64> {
+        let elements = self.meta.elements;
+        (elements > 0).then(|| elements as f64 / self.summary.median * 1e9)
+    }
+
+    /// `counter` per element, if it was read
+    pub fn per_element(&self, counter: &str) -> Option<f64> {
+        let (_, count) = self.counters.iter().find(|(c, _)| c == counter)?;
+        (self.meta.elements > 0).then(|| count / self.meta.elements as f64)
+    }
+
+    /// cycles per element at the median, if there is a cycle counter
+    pub fn cycles_per_element(&self) -> Option<f64> {
+        let elements = self.meta.elements;
+        (elements > 0).then_some(())?;
+        Some(self.summary.median * self.cycles_per_ns? / elements as f64)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let meta = &self.meta;
+        let s = &self.summary;
+        Value::object([
+            ("name", meta.name.as_str().into()),
+            ("module", meta.module.as_str().into()),
+            ("tags", meta.tags[..].into()),
+            ("layout", meta.layout.as_str().into()),
+            ("fields", meta.fields.into()),
+            ("touched", meta.touched[..].into()),
+            ("sparse", meta.is_sparse().into()),
+            ("n", meta.n.into()),
+            ("m", meta.m.into()),
+            ("elements", meta.elements.into()),
+            ("bytes_read", meta.bytes_read.into()),
+            ("bytes_written", meta.bytes_written.into()),
+            (
+                "ops",
+                Value::object(meta.ops.counts().map(|(op, n)| (op, n.into()))),
+            ),
+            ("variant", meta.variant.as_str().into()),
+            ("rotation", meta.rotation.as_str().into()),
+            ("cache", meta.cache.as_str().into()),
+            ("simd", meta.simd.as_str().into()),
+            ("iters", self.iters.into()),
+            (
+                "summary",
+                Value::object([
+                    ("samples", s.samples.into()),
+                    ("mean", s.mean.into()),
+                    ("std_dev", s.std_dev.into()),
+                    ("median", s.median.into()),
+                    ("mad", s.mad.into()),
+                    ("min", s.min.into()),
+                    ("max", s.max.into()),
+                    ("p5", s.p5.into()),
+                    ("p25", s.p25.into()),
+                    ("p75", s.p75.into()),
+                    ("p95", s.p95.into()),
+                    ("median_ci", [s.median_ci.0, s.median_ci.1][..].into()),
+                    ("outliers_mild", s.outliers.mild().into()),
+                    ("outliers_severe", s.outliers.severe().into()),
+                ]),
+            ),
+            ("cycles_per_ns", self.cycles_per_ns.into()),
+            (
+                "throughput",
+                Value::object([
+                    ("bytes_per_s", self.bytes_per_s().into()),
+                    ("elements_per_s", self.elements_per_s().into()),
+                    ("cycles_per_element", self.cycles_per_element().into()),
+                ]),
+            ),
+            (
+                "counters",
+                Value::object(self.counters.iter().map(|(c, n)| (c.as_str(), (*n).into()))),
+            ),
+            ("samples", self.samples[..].into()),
+        ])
+    }
+
+    /// Reads back [`Record::to_json`]. The summary is recomputed from the raw
+    /// samples, which gives the same values, and so is the throughput.
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let string = |key| value.get(key)?.as_str().map(str::to_string);
+        let tags = value
+            .get("tags")?
+            .as_array()?
+            .iter()
+            .map(|t| t.as_str().map(str::to_string))
+            .collect::<Option<_>>()?;
+        let ops = value.get("ops")?;
+        let op = |key| ops.get(key)?.as_usize();
+        let number = |key| value.get(key)?.as_usize();
+        let samples = value
+            .get("samples")?
+            .as_array()?
+            .iter()
+            .map(Value::as_f64)
+            .collect::<Option<Vec<_>>>()?;
+        let meta = Meta {
+            name: string("name")?,
+            module: string("module")?,
+            tags,
+            layout: string("layout")?,
+            fields: number("fields")?,
+            touched: value
+                .get("touched")?
+                .as_array()?
+                .iter()
+                .map(Value::as_usize)
+                .collect::<Option<_>>()?,
+            n: number("n")?,
+            m: number("m")?,
+            elements: number("elements")?,
+            bytes_read: number("bytes_read")?,
+            bytes_written: number("bytes_written")?,
+            ops: Ops {
+                add: op("add")?,
+                mul: op("mul")?,
+                mul_add: op("mul_add")?,
+                sqrt: op("sqrt")?,
+                div: op("div")?,
+            },
+            variant: string("variant")?,
+            rotation: string("rotation")?,
+            cache: string("cache")?,
+            simd: string("simd")?,
+        };
+        if samples.is_empty() {
+            return None;
+        }
+        Some(Record {
+            meta,
+            iters: number("iters")? as u64,
+            summary: Summary::new(&samples),
+            samples,
+            cycles_per_ns: value.get("cycles_per_ns").and_then(Value::as_f64),
+            counters: value
+                .get("counters")?
+                .as_object()?
+                .iter()
+                .map(|(c, n)| Some((c.clone(), n.as_f64()?)))
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    /// columns of [`Record::csv_row`]
+    pub const CSV_HEADER: [&'static str; 39] = [
+        "name",
+        "module",
+        "tags",
+        "layout",
+        "fields",
+        "touched",
+        "sparse",
+        "n",
+        "m",
+        "elements",
+        "bytes_read",
+        "bytes_written",
+        "ops",
+        "variant",
+        "rotation",
+        "cache",
+        "simd",
+        "iters",
+        "samples",
+        "mean",
+        "std_dev",
+        "median",
+        "mad",
+        "min",
+        "max",
+        "p5",
+        "p25",
+        "p75",
+        "p95",
+        "median_ci_low",
+        "median_ci_high",
+        "outliers_mild",
+        "outliers_severe",
+        "cycles_per_ns",
+        "bytes_per_s",
+        "elements_per_s",
+        "cycles_per_element",
+        "counters",
+        "samples_ns",
+    ];
+
+    /// Lists (tags, touched fields, operations and counters as `name=count`,
+    /// raw samples) are space separated within their column, missing values
+    /// empty.
+    pub fn csv_row(&self) -> [String; 39] {
+        let meta = &self.meta;
+        let s = &self.summary;
+        let list = |v: &mut dyn Iterator<Item = String>| v.collect::<Vec<_>>().join(" ");
+        let optional = |x: Option<f64>| x.map_or(String::new(), |x| x.to_string());
+        [
+            meta.name.clone(),
+            meta.module.clone(),
+            meta.tags.join(" "),
+            meta.layout.clone(),
+            meta.fields.to_string(),
+            list(&mut meta.touched.iter().map(ToString::to_string)),
+            meta.is_sparse().to_string(),
+            meta.n.to_string(),
+            meta.m.to_string(),
+            meta.elements.to_string(),
+            meta.bytes_read.to_string(),
+            meta.bytes_written.to_string(),
+            meta.ops.to_string(),
+            meta.variant.clone(),
+            meta.rotation.clone(),
+            meta.cache.clone(),
+            meta.simd.clone(),
+            self.iters.to_string(),
+            s.samples.to_string(),
+            s.mean.to_string(),
+            s.std_dev.to_string(),
+            s.median.to_string(),
+            s.mad.to_string(),
+            s.min.to_string(),
+            s.max.to_string(),
+            s.p5.to_string(),
+            s.p25.to_string(),
+            s.p75.to_string(),
+            s.p95.to_string(),
+            s.median_ci.0.to_string(),
+            s.median_ci.1.to_string(),
+            s.outliers.mild().to_string(),
+            s.outliers.severe().to_string(),
+            optional(self.cycles_per_ns),
+            optional(self.bytes_per_s()),
+            optional(self.elements_per_s()),
+            optional(self.cycles_per_element()),
+            list(&mut self.counters.iter().map(|(c, n)| format!("{c}={n}"))),
+            list(&mut self.samples.iter().map(ToString::to_string)),
+        ]
+    }
+}
+
+/// one JSON object per line
+pub fn write_jsonl(w: &mut impl Write, records: &[Record]) -> io::Result<()> {
+    for record in records {
+        writeln!(w, "{}", record.to_json())?;
+    }
+    Ok(())
+}
+
+/// Reads records written by [`write_jsonl`], skipping blank lines.
+pub fn read_jsonl(r: impl BufRead) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let value = json::parse(&line).map_err(|e| invalid(format!("line {}: {e}", i + 1)))?;
+        let record = Record::from_json(&value)
+            .ok_or_else(|| invalid(format!("line {}: not a benchmark record", i + 1)))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// header and one row per record
+pub fn write_csv(w: &mut impl Write, records: &[Record]) -> io::Result<()> {
+    write_csv_row(w, &Record::CSV_HEADER)?;
+    for record in records {
+        write_csv_row(w, &record.csv_row())?;
+    }
+    Ok(())
+}
+
+fn write_csv_row(w: &mut impl Write, row: &[impl AsRef<str>]) -> io::Result<()> {
+    for (i, field) in row.iter().enumerate() {
+        if i > 0 {
+            w.write_all(b",")?;
+        }
+        w.write_all(csv_field(field.as_ref()).as_bytes())?;
+    }
+    w.write_all(b"\n")
+}
+
+/// quotes `s` if it contains a separator, quote or line break (RFC 4180)
+fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// the directory named by [`OUT_VAR`], if set
+pub fn out_dir() -> Option<PathBuf> {
+    env::var_os(OUT_VAR)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// [`append`] to [`out_dir`], nothing if it is not set
+pub fn emit(record: &Record) -> io::Result<()> {
+    match out_dir() {
+        Some(dir) => append(&dir, record),
+        None => Ok(()),
+    }
+}
+
+/// Appends `record` to `results.jsonl` and `results.csv` in `dir`, writing the
+/// CSV header if the file is new.
+pub fn append(dir: &Path, record: &Record) -> io::Result<()> {
+    // benchmarks run as tests are executed on several threads
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    fs::create_dir_all(dir)?;
+    let open = |name| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(name))
+    };
+    write_jsonl(&mut open("results.jsonl")?, std::slice::from_ref(record))?;
+    let mut csv = open("results.csv")?;
+    if csv.metadata()?.len() == 0 {
+        write_csv_row(&mut csv, &Record::CSV_HEADER)?;
+    }
+    write_csv_row(&mut csv, &record.csv_row())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        counters::Counter,
+        layout::{SoA, SPARSE_7},
+    };
+
+    fn record() -> Record {
+        let meta = Meta {
+            simd: "scalar".to_string(),
+            ..Meta::layout::<SoA<7>, 7>(&SPARSE_7, 256, 300).with_variant("l1")
+        };
+        let measurement = Measurement {
+            iters: 10,
+            samples: vec![2.0, 1.0, 3.0],
+            counters: vec![(Counter::Instructions, 2560.0), (Counter::L1dMisses, 64.0)],
+        };
+        Record::new(meta, &measurement)
+    }
+
+    #[test]
+    fn test_meta() {
+        let meta = record().meta;
+        assert_eq!(meta.name, "sparse_7_soa_l1");
+        assert!(meta.is_sparse());
+        assert!(meta.has_tag("sparse"));
+        assert!(!Meta::kernel(
+            "bench_runtime",
+            "runtime",
+            "soa",
+            5,
+            &[0, 1, 2, 3, 4],
+            256,
+            300
+        )
+        .is_sparse());
+    }
+
+    #[test]
+    fn test_throughput() {
+        // 256 elements reading 4 and writing 1 Cluster of 32 bytes in 2 ns
+        let mut record = record();
+        record.cycles_per_ns = Some(3.0);
+        assert_eq!(record.meta.bytes(), 256 * 160);
+        assert_eq!(record.bytes_per_s(), Some(256.0 * 160.0 / 2.0 * 1e9));
+        assert_eq!(record.elements_per_s(), Some(128e9));
+        assert_eq!(record.cycles_per_element(), Some(6.0 / 256.0));
+        assert_eq!(record.per_element("instructions"), Some(10.0));
+        assert_eq!(record.per_element("cycles"), None);
+        record.cycles_per_ns = None;
+        record.meta.bytes_read = 0;
+        record.meta.bytes_written = 0;
+        assert_eq!(record.bytes_per_s(), None);
+        assert_eq!(record.cycles_per_element(), None);
+    }
+
+    #[test]
+    fn test_json() {
+        let json = record().to_json().to_string();
+        assert!(json.starts_with(
+            r#"{"name":"sparse_7_soa_l1","module":"layout","tags":["sparse"],"layout":"soa","fields":7,"touched":[0,2,3,6],"sparse":true,"n":256,"m":300,"elements":256,"bytes_read":128,"bytes_written":32,"ops":{"add":1,"mul":1,"mul_add":1,"sqrt":0,"div":0},"variant":"l1","rotation":"stride:7","cache":"warm","simd":"scalar","iters":10,"summary":{"samples":3,"mean":2,"#
+        ));
+        assert!(json.ends_with(r#""samples":[2,1,3]}"#));
+    }
+
+    #[test]
+    fn test_read_jsonl() {
+        let mut out = Vec::new();
+        write_jsonl(&mut out, &[record(), record()]).unwrap();
+        out.extend_from_slice(b"\n");
+        assert_eq!(read_jsonl(&out[..]).unwrap(), vec![record(), record()]);
+
+        let old = r#"{"name":"a","layout":"soa","fields":1,"touched":[0],"n":1,"m":1,"variant":"","iters":1,"samples":[1]}"#;
+        let err = read_jsonl(old.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: not a benchmark record");
+
+        let err = read_jsonl(&b"{\"name\":\"x\"}\n"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: not a benchmark record");
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+        assert_eq!(csv_field("ab"), "ab");
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &[record()]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("name,module,tags,layout,fields,touched,sparse,n,m,"));
+        assert!(lines[1].starts_with(
+            "sparse_7_soa_l1,layout,sparse,soa,7,0 2 3 6,true,256,300,256,128,32,add=1 mul=1 mul_add=1,l1,stride:7,warm,scalar,10,3,"
+        ));
+        assert!(lines[1].ends_with(",instructions=2560 l1d_misses=64,2 1 3"));
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = env::temp_dir().join(format!("cache_bench_report_{}", std::process::id()));
+        append(&dir, &record()).unwrap();
+        append(&dir, &record()).unwrap();
+        let jsonl = fs::read_to_string(dir.join("results.jsonl")).unwrap();
+        let csv = fs::read_to_string(dir.join("results.csv")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(jsonl.lines().count(), 2);
+        assert_eq!(csv.lines().count(), 3);
+    }
+}
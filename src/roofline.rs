@@ -0,0 +1,401 @@
+//! Roofline model: every benchmark placed between the machine's peak
+//! bandwidth and its peak FLOP rate, to tell memory-bound kernels from
+//! compute-bound ones.
+//!
+//! Kernels declare the operations they do per element as [`Ops`] on
+//! `Cluster`s. A `mul_add` counts as 2 FLOPs per lane, `sqrt` and division
+//! as set by [`Weights`]. The peaks are measured: the bandwidth of every
+//! cache level and of memory by the [`stream`] kernels, and the FLOP rate of
+//! independent `mul_add` chains. A benchmark is held against the bandwidth
+//! of the level its rotation fits in.
+
+use std::{fmt, hint::black_box};
+
+use crate::{
+    cache::CacheMode,
+    harness::{self, Config, Rotation},
+    report::{Meta, Record},
+    simd::StdFloat,
+    stream::{self, Peak},
+    topology::{CacheTopology, Target},
+    utils::Cluster,
+};
+
+/// `f32` lanes of a `Cluster`
+pub const LANES: usize = size_of::<Cluster>() / size_of::<f32>();
+
+/// floating point operations on `Cluster`s per element
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ops {
+    pub add: usize,
+    pub mul: usize,
+    pub mul_add: usize,
+    pub sqrt: usize,
+    pub div: usize,
+}
+
+impl Ops {
+    pub const NONE: Ops = Ops {
+        add: 0,
+        mul: 0,
+        mul_add: 0,
+        sqrt: 0,
+        div: 0,
+    };
+
+    /// [`chain`](crate::layout::chain) of `k` values and the running sum
+    pub fn chain(k: usize) -> Self {
+        Ops {
+            add: 1,
+            mul: usize::from(k >= 2 && k.is_multiple_of(2)),
+            mul_add: k.saturating_sub(1) / 2,
+            ..Ops::NONE
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Ops::NONE
+    }
+
+    /// FLOPs per element over all lanes
+    pub fn flops(&self, weights: &Weights) -> f64 {
+        let ops = (self.add + self.mul + 2 * self.mul_add) as f64
+            + self.sqrt as f64 * weights.sqrt
+            + self.div as f64 * weights.div;
+        ops * LANES as f64
+    }
+
+    /// every operation with its count, in declaration order
+    pub fn counts(&self) -> [(&'static str, usize); 5] {
+        [
+            ("add", self.add),
+            ("mul", self.mul),
+            ("mul_add", self.mul_add),
+            ("sqrt", self.sqrt),
+            ("div", self.div),
+        ]
+    }
+}
+
+/// `add=1 mul_add=4`, the operations done at least once
+impl fmt::Display for Ops {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops: Vec<String> = self
+            .counts()
+            .iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(op, n)| format!("{op}={n}"))
+            .collect();
+        f.write_str(&ops.join(" "))
+    }
+}
+
+/// FLOPs per lane of the operations that are not a plain add or multiply
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    pub sqrt: f64,
+    pub div: f64,
+}
+
+/// one FLOP each, as most tools count them
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            sqrt: 1.0,
+            div: 1.0,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// peaks of the machine, measured on one core
+#[derive(Clone, Debug, PartialEq)]
+pub struct Machine {
+    /// FLOP/s of independent `mul_add` chains
+    pub flops: f64,
+    /// sustainable bytes/s of every cache level, then memory
+    pub bandwidths: Vec<(Target, f64)>,
+}
+
+/// independent `mul_add` chains of the FLOP rate kernel, enough to hide the
+/// latency
+const CHAINS: usize = 8;
+
+/// `mul_add`s per chain and iteration
+const STEPS: usize = 256;
+
+impl Machine {
+    /// the FLOP rate and the bandwidths [calibrated](stream::calibrate) for
+    /// `topology`, timed with `config`
+    pub fn measure(config: &Config, topology: &CacheTopology) -> Self {
+        Self::with_peaks(config, &stream::calibrate(config, topology))
+    }
+
+    /// the FLOP rate and the bandwidths of `peaks`
+    pub fn with_peaks(config: &Config, peaks: &[Peak]) -> Self {
+        Machine {
+            flops: peak_flops(config),
+            bandwidths: peaks.iter().map(|p| (p.target, p.bandwidth)).collect(),
+        }
+    }
+
+    pub fn bandwidth(&self, target: Target) -> Option<f64> {
+        self.bandwidths
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map(|&(_, bandwidth)| bandwidth)
+    }
+}
+
+fn peak_flops(config: &Config) -> f64 {
+    let (x, y) = (Cluster::splat(0.999), Cluster::splat(0.001));
+    let m = harness::run(config, || {
+        let (x, y) = (black_box(x), black_box(y));
+        let mut acc = [Cluster::splat(1.0); CHAINS];
+        for _ in 0..STEPS {
+            for a in &mut acc {
+                *a = a.mul_add(x, y);
+            }
+        }
+        acc
+    });
+    (CHAINS * STEPS * 2 * LANES) as f64 / m.median() * 1e9
+}
+
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Memory,
+    Compute,
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bound::Memory => "memory",
+            Bound::Compute => "compute",
+        })
+    }
+}
+
+/// a benchmark on the roofline
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    /// FLOPs per byte read or written
+    pub intensity: f64,
+    /// FLOP/s at the median
+    pub flops: f64,
+    /// the level whose bandwidth is the roof
+    pub ceiling: Target,
+    /// the lower of the peak FLOP rate and the intensity times the bandwidth
+    /// of the ceiling
+    pub attainable: f64,
+    pub bound: Bound,
+}
+
+impl Point {
+    /// Places `record` with the FLOPs of its [`Ops`] counted by `weights`.
+    /// `None` if it declares no operations or no bytes.
+    pub fn place(
+        record: &Record,
+        machine: &Machine,
+        topology: &CacheTopology,
+        weights: &Weights,
+    ) -> Option<Self> {
+        let meta = &record.meta;
+        let flops = meta.ops.flops(weights);
+        let bytes = meta.bytes_read + meta.bytes_written;
+        if flops == 0.0 || bytes == 0 {
+            return None;
+        }
+        let intensity = flops / bytes as f64;
+        let ceiling = residence(meta, topology);
+        let roof = intensity * machine.bandwidth(ceiling)?;
+        Some(Point {
+            intensity,
+            flops: flops * meta.elements as f64 / record.summary.median * 1e9,
+            ceiling,
+            attainable: roof.min(machine.flops),
+            bound: if roof < machine.flops {
+                Bound::Memory
+            } else {
+                Bound::Compute
+            },
+        })
+    }
+
+    /// share of the attainable FLOP rate reached
+    pub fn efficiency(&self) -> f64 {
+        self.flops / self.attainable
+    }
+}
+
+/// Where the data of a benchmark lives when an iteration starts: memory when
+/// cold, else the smallest data cache holding the bytes it declares for every
+/// dataset its rotation visits.
+pub fn residence(meta: &Meta, topology: &CacheTopology) -> Target {
+    if let Some(CacheMode::Cold(_)) = CacheMode::parse(&meta.cache) {
+        return Target::Memory;
+    }
+    let datasets = match Rotation::parse(&meta.rotation) {
+        Some(rotation) if meta.m > 0 => {
+            let mut indices = rotation.indices(meta.m);
+            indices.sort_unstable();
+            indices.dedup();
+            indices.len()
+        }
+        _ => meta.m,
+    };
+    let bytes = meta.bytes() * datasets;
+    (1..)
+        .map_while(|level| topology.data_cache(level))
+        .find(|cache| cache.size >= bytes)
+        .map_or(Target::Memory, |cache| Target::Level(cache.level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        harness::Measurement,
+        layout::{SoA, SPARSE_9},
+        topology::{CacheKind, CacheLevel},
+    };
+
+    fn machine() -> Machine {
+        Machine {
+            flops: 100e9,
+            bandwidths: vec![
+                (Target::Level(1), 200e9),
+                (Target::Level(2), 100e9),
+                (Target::Level(3), 50e9),
+                (Target::Memory, 10e9),
+            ],
+        }
+    }
+
+    fn record(meta: Meta, ns: f64) -> Record {
+        let measurement = Measurement {
+            iters: 1,
+            samples: vec![ns],
+            counters: Vec::new(),
+        };
+        Record::new(meta, &measurement)
+    }
+
+    #[test]
+    fn test_ops() {
+        assert_eq!(
+            Ops::chain(1),
+            Ops {
+                add: 1,
+                ..Ops::NONE
+            }
+        );
+        assert_eq!(Ops::chain(4).to_string(), "add=1 mul=1 mul_add=1");
+        assert_eq!(Ops::chain(9).to_string(), "add=1 mul_add=4");
+        let ops = Ops {
+            add: 1,
+            mul_add: 1,
+            sqrt: 1,
+            div: 1,
+            ..Ops::NONE
+        };
+        assert_eq!(ops.flops(&Weights::default()), 5.0 * 8.0);
+        assert_eq!(
+            ops.flops(&Weights {
+                sqrt: 4.0,
+                div: 4.0
+            }),
+            11.0 * 8.0
+        );
+        assert!(Ops::NONE.is_empty() && !ops.is_empty());
+    }
+
+    #[test]
+    fn test_place() {
+        let topology = CacheTopology::fallback();
+        // 6 FLOPs per lane on 6 Clusters read and 1 written: 48 / 224 FLOP/byte
+        let meta = Meta::layout::<SoA<9>, 9>(&SPARSE_9, 256, 100);
+        assert_eq!(residence(&meta, &topology), Target::Level(3));
+        let point = Point::place(
+            &record(meta.clone(), 1000.0),
+            &machine(),
+            &topology,
+            &Weights::default(),
+        )
+        .unwrap();
+        assert_eq!(point.intensity, 48.0 / 224.0);
+        assert_eq!(point.flops, 48.0 * 256.0 / 1000.0 * 1e9);
+        assert_eq!(point.ceiling, Target::Level(3));
+        assert_eq!(point.attainable, 48.0 / 224.0 * 50e9);
+        assert_eq!(point.bound, Bound::Memory);
+
+        // one dataset of 56K in L2, then cold in memory
+        let hot = Meta {
+            rotation: Rotation::Same.to_string(),
+            ..meta.clone()
+        };
+        assert_eq!(residence(&hot, &topology), Target::Level(2));
+        let cold = Meta {
+            cache: "cold:sweep".to_string(),
+            ..hot
+        };
+        assert_eq!(residence(&cold, &topology), Target::Memory);
+
+        let compute = Meta {
+            m: 1,
+            n: 16,
+            elements: 16,
+            ..meta.clone()
+        }
+        .with_bytes(32, 0);
+        let point = Point::place(
+            &record(compute, 10.0),
+            &machine(),
+            &topology,
+            &Weights::default(),
+        )
+        .unwrap();
+        assert_eq!(point.ceiling, Target::Level(1));
+        assert_eq!(point.bound, Bound::Compute);
+        assert_eq!(point.attainable, 100e9);
+
+        let meta = Meta {
+            ops: Ops::NONE,
+            ..meta
+        };
+        assert_eq!(
+            Point::place(
+                &record(meta, 1.0),
+                &machine(),
+                &topology,
+                &Weights::default()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_measure() {
+        let topology = CacheTopology {
+            caches: vec![CacheLevel {
+                level: 1,
+                kind: CacheKind::Data,
+                size: 4 << 10,
+                line_size: 64,
+                ways: 4,
+                sets: 16,
+                shared_cpus: vec![0],
+            }],
+        };
+        let machine = Machine::measure(&Config::quick(), &topology);
+        assert!(machine.flops > 0.0);
+        let targets: Vec<Target> = machine.bandwidths.iter().map(|&(t, _)| t).collect();
+        assert_eq!(targets, [Target::Level(1), Target::Memory]);
+        assert!(machine.bandwidths.iter().all(|&(_, b)| b > 0.0));
+        assert_eq!(machine.bandwidth(Target::Level(2)), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! `Cluster`, eight `f32` lanes. `std::simd` on nightly, a plain array with
+//! the same alignment and the handful of operations the kernels use on
+//! stable.
+
+/// how `Cluster` is implemented, recorded with every benchmark since the
+/// scalar fallback is not comparable to `std::simd`
+#[cfg(nightly)]
+pub const BACKEND: &str = "std::simd";
+
+#[cfg(not(nightly))]
+pub const BACKEND: &str = "scalar";
+
+#[cfg(nightly)]
+pub use std::simd::{num::SimdFloat, StdFloat};
+
+#[cfg(nightly)]
+pub type Cluster = std::simd::Simd<f32, 8>;
+
+#[cfg(not(nightly))]
+pub use fallback::{Cluster, SimdFloat, StdFloat};
+
+#[cfg(not(nightly))]
+mod fallback {
+    use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+    pub const LANES: usize = 8;
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    #[repr(C, align(32))]
+    pub struct Cluster([f32; LANES]);
+
+    impl Cluster {
+        #[inline(always)]
+        pub const fn splat(v: f32) -> Self {
+            Cluster([v; LANES])
+        }
+
+        #[inline(always)]
+        pub const fn from_array(lanes: [f32; LANES]) -> Self {
+            Cluster(lanes)
+        }
+
+        #[inline(always)]
+        pub const fn to_array(self) -> [f32; LANES] {
+            self.0
+        }
+
+        #[inline(always)]
+        fn map(self, f: impl Fn(f32) -> f32) -> Self {
+            Cluster(self.0.map(f))
+        }
+
+        #[inline(always)]
+        fn zip(self, other: Self, f: impl Fn(f32, f32) -> f32) -> Self {
+            Cluster(std::array::from_fn(|i| f(self.0[i], other.0[i])))
+        }
+    }
+
+    /// the subset of `std::simd::StdFloat` used by the kernels
+    pub trait StdFloat {
+        fn mul_add(self, a: Self, b: Self) -> Self;
+        fn sqrt(self) -> Self;
+    }
+
+    impl StdFloat for Cluster {
+        #[inline(always)]
+        fn mul_add(self, a: Self, b: Self) -> Self {
+            Cluster(std::array::from_fn(|i| self.0[i].mul_add(a.0[i], b.0[i])))
+        }
+
+        #[inline(always)]
+        fn sqrt(self) -> Self {
+            self.map(f32::sqrt)
+        }
+    }
+
+    /// the subset of `std::simd::num::SimdFloat` used by the kernels
+    pub trait SimdFloat {
+        type Scalar;
+        fn reduce_sum(self) -> Self::Scalar;
+    }
+
+    impl SimdFloat for Cluster {
+        type Scalar = f32;
+
+        #[inline(always)]
+        fn reduce_sum(self) -> f32 {
+            self.0.iter().sum()
+        }
+    }
+
+    impl Add for Cluster {
+        type Output = Self;
+
+        #[inline(always)]
+        fn add(self, rhs: Self) -> Self {
+            self.zip(rhs, |a, b| a + b)
+        }
+    }
+
+    impl AddAssign for Cluster {
+        #[inline(always)]
+        fn add_assign(&mut self, rhs: Self) {
+            *self = *self + rhs;
+        }
+    }
+
+    impl Sub for Cluster {
+        type Output = Self;
+
+        #[inline(always)]
+        fn sub(self, rhs: Self) -> Self {
+            self.zip(rhs, |a, b| a - b)
+        }
+    }
+
+    impl Mul for Cluster {
+        type Output = Self;
+
+        #[inline(always)]
+        fn mul(self, rhs: Self) -> Self {
+            self.zip(rhs, |a, b| a * b)
+        }
+    }
+
+    impl Div for Cluster {
+        type Output = Self;
+
+        #[inline(always)]
+        fn div(self, rhs: Self) -> Self {
+            self.zip(rhs, |a, b| a / b)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster() {
+        assert_eq!(size_of::<Cluster>(), 32);
+        assert_eq!(align_of::<Cluster>(), 32);
+        let a = Cluster::splat(4.0);
+        let c = Cluster::splat(9.0);
+        let d = Cluster::splat(1.0);
+        let e = Cluster::splat(2.0);
+        assert_eq!(a.mul_add(c.sqrt(), d / e), Cluster::splat(12.5));
+        assert_eq!((a * c).reduce_sum(), 288.0);
+    }
+}
@@ -0,0 +1,254 @@
+//! Robust summaries of benchmark samples: median, MAD, percentiles, bootstrap
+//! confidence intervals and outlier classification.
+
+use std::fmt;
+
+use crate::rng::Rng;
+
+/// number of bootstrap resamples
+pub const RESAMPLES: usize = 1000;
+
+/// confidence level of [`Summary::median_ci`]
+pub const CONFIDENCE: f64 = 0.95;
+
+/// `1 / Φ⁻¹(3/4)`, scales the MAD to estimate the standard deviation of
+/// normally distributed samples
+const MAD_SCALE: f64 = 1.4826;
+
+/// samples outside the Tukey fences, `1.5 IQR` (mild) and `3 IQR` (severe)
+/// beyond the quartiles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Outliers {
+    pub low_severe: usize,
+    pub low_mild: usize,
+    pub high_mild: usize,
+    pub high_severe: usize,
+}
+
+impl Outliers {
+    pub fn classify(sorted: &[f64]) -> Self {
+        let q1 = percentile(sorted, 25.0);
+        let q3 = percentile(sorted, 75.0);
+        let iqr = q3 - q1;
+        let mut outliers = Outliers::default();
+        for &x in sorted {
+            if x < q1 - 3.0 * iqr {
+                outliers.low_severe += 1;
+            } else if x < q1 - 1.5 * iqr {
+                outliers.low_mild += 1;
+            } else if x > q3 + 3.0 * iqr {
+                outliers.high_severe += 1;
+            } else if x > q3 + 1.5 * iqr {
+                outliers.high_mild += 1;
+            }
+        }
+        outliers
+    }
+
+    pub fn mild(&self) -> usize {
+        self.low_mild + self.high_mild
+    }
+
+    pub fn severe(&self) -> usize {
+        self.low_severe + self.high_severe
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub samples: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub median: f64,
+    /// median absolute deviation, scaled to be comparable to `std_dev`
+    pub mad: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p5: f64,
+    pub p25: f64,
+    pub p75: f64,
+    pub p95: f64,
+    /// bootstrap confidence interval of the median at [`CONFIDENCE`]
+    pub median_ci: (f64, f64),
+    pub outliers: Outliers,
+}
+
+impl Summary {
+    /// Summarizes `samples`, resampling with a fixed seed so that the same
+    /// samples always give the same interval. Panics on empty `samples`.
+    pub fn new(samples: &[f64]) -> Self {
+        assert!(!samples.is_empty(), "no samples to summarize");
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let var = sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+        let median = percentile(&sorted, 50.0);
+        let mut deviations: Vec<f64> = sorted.iter().map(|x| (x - median).abs()).collect();
+        deviations.sort_by(f64::total_cmp);
+        Summary {
+            samples: sorted.len(),
+            mean,
+            std_dev: var.sqrt(),
+            median,
+            mad: percentile(&deviations, 50.0) * MAD_SCALE,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            p5: percentile(&sorted, 5.0),
+            p25: percentile(&sorted, 25.0),
+            p75: percentile(&sorted, 75.0),
+            p95: percentile(&sorted, 95.0),
+            median_ci: bootstrap_median_ci(&sorted, RESAMPLES, CONFIDENCE, &mut Rng::new(0)),
+            outliers: Outliers::classify(&sorted),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (lo, hi) = self.median_ci;
+        write!(
+            f,
+            "median {:.1} ns [{lo:.1}, {hi:.1}], MAD {:.1}, p5 {:.1}, p95 {:.1}",
+            self.median, self.mad, self.p5, self.p95
+        )?;
+        let o = &self.outliers;
+        if o.mild() + o.severe() > 0 {
+            write!(
+                f,
+                ", outliers {} mild {} severe of {}",
+                o.mild(),
+                o.severe(),
+                self.samples
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `p`-th percentile of `sorted`, interpolating linearly between samples
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.len() == 1 {
+        return sorted[0];
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+pub fn median(samples: &[f64]) -> f64 {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    percentile(&sorted, 50.0)
+}
+
+/// percentile bootstrap interval of the median
+pub fn bootstrap_median_ci(
+    samples: &[f64],
+    resamples: usize,
+    confidence: f64,
+    rng: &mut Rng,
+) -> (f64, f64) {
+    let mut medians = Vec::with_capacity(resamples);
+    let mut resample = vec![0.0; samples.len()];
+    for _ in 0..resamples {
+        for x in resample.iter_mut() {
+            *x = samples[rng.below(samples.len())];
+        }
+        resample.sort_by(f64::total_cmp);
+        medians.push(percentile(&resample, 50.0));
+    }
+    medians.sort_by(f64::total_cmp);
+    let tail = (1.0 - confidence) / 2.0 * 100.0;
+    (
+        percentile(&medians, tail),
+        percentile(&medians, 100.0 - tail),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// the confidence intervals of the medians overlap
+    Indistinguishable,
+    /// `a` is faster, its median takes `ratio` times the time of `b`
+    Faster { ratio: f64 },
+    /// `a` is slower, its median takes `ratio` times the time of `b`
+    Slower { ratio: f64 },
+}
+
+/// Compares the medians of `a` and `b`, only calling them different if their
+/// confidence intervals do not overlap.
+pub fn compare(a: &Summary, b: &Summary) -> Comparison {
+    let ratio = a.median / b.median;
+    if a.median_ci.1 < b.median_ci.0 {
+        Comparison::Faster { ratio }
+    } else if a.median_ci.0 > b.median_ci.1 {
+        Comparison::Slower { ratio }
+    } else {
+        Comparison::Indistinguishable
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Indistinguishable => write!(f, "no significant difference"),
+            Comparison::Faster { ratio } => write!(f, "{:.1}% faster", (1.0 - ratio) * 100.0),
+            Comparison::Slower { ratio } => write!(f, "{:.1}% slower", (ratio - 1.0) * 100.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 3.0);
+        assert_eq!(percentile(&sorted, 100.0), 5.0);
+        assert_eq!(percentile(&sorted, 62.5), 3.5);
+        assert_eq!(median(&[4.0, 1.0, 3.0, 2.0]), 2.5);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut samples: Vec<f64> = (0..100).map(|i| 100.0 + (i % 10) as f64).collect();
+        samples.push(200.0);
+        samples.push(50.0);
+        samples.push(120.0);
+        let s = Summary::new(&samples);
+        assert_eq!(s.samples, 103);
+        assert_eq!(s.median, 105.0);
+        assert_eq!((s.min, s.max), (50.0, 200.0));
+        assert_eq!(s.mad, 3.0 * MAD_SCALE);
+        assert!(s.median_ci.0 <= s.median && s.median <= s.median_ci.1);
+        assert_eq!(
+            s.outliers,
+            Outliers {
+                low_severe: 1,
+                low_mild: 0,
+                high_mild: 1,
+                high_severe: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let fast: Vec<f64> = (0..50).map(|i| 100.0 + (i % 20) as f64).collect();
+        let slow: Vec<f64> = (0..50).map(|i| 130.0 + (i % 20) as f64).collect();
+        let close: Vec<f64> = (0..50).map(|i| 101.0 + (i % 20) as f64).collect();
+        let (fast, slow, close) = (
+            Summary::new(&fast),
+            Summary::new(&slow),
+            Summary::new(&close),
+        );
+        assert!(matches!(compare(&fast, &slow), Comparison::Faster { .. }));
+        assert!(matches!(compare(&slow, &fast), Comparison::Slower { .. }));
+        assert_eq!(compare(&fast, &close), Comparison::Indistinguishable);
+    }
+}
@@ -0,0 +1,302 @@
+//! STREAM-style bandwidth calibration: the Copy, Scale, Add and Triad
+//! kernels over `Cluster` arrays, swept over working-set sizes, give the
+//! sustainable bandwidth of every cache level and of memory.
+//!
+//! Bytes are counted the STREAM way, every array element read or written
+//! once, without the write-allocate reads of the stored lines.
+
+use std::{fmt, hint::black_box};
+
+use crate::{
+    harness::{self, Config},
+    simd::StdFloat,
+    sweep::geometric,
+    topology::{CacheTopology, Target},
+    utils::Cluster,
+};
+
+/// the scalar of Scale and Triad
+const Q: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    /// `c = a`
+    Copy,
+    /// `c = q * a`
+    Scale,
+    /// `c = a + b`
+    Add,
+    /// `c = a + q * b`
+    Triad,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 4] = [Kernel::Copy, Kernel::Scale, Kernel::Add, Kernel::Triad];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kernel::Copy => "copy",
+            Kernel::Scale => "scale",
+            Kernel::Add => "add",
+            Kernel::Triad => "triad",
+        }
+    }
+
+    /// arrays read per element, one is written
+    fn inputs(self) -> usize {
+        match self {
+            Kernel::Copy | Kernel::Scale => 1,
+            Kernel::Add | Kernel::Triad => 2,
+        }
+    }
+
+    /// arrays the kernel touches
+    pub fn arrays(self) -> usize {
+        self.inputs() + 1
+    }
+
+    /// bytes read and written per element
+    pub fn bytes_per_element(self) -> usize {
+        self.arrays() * size_of::<Cluster>()
+    }
+
+    /// one pass over `c`, which is as long as `a` and `b`
+    #[inline(never)]
+    pub fn run(self, a: &[Cluster], b: &[Cluster], c: &mut [Cluster]) {
+        let q = Cluster::splat(Q);
+        let (a, b) = (&a[..c.len()], &b[..c.len()]);
+        match self {
+            Kernel::Copy => c.copy_from_slice(a),
+            Kernel::Scale => {
+                for (c, a) in c.iter_mut().zip(a) {
+                    *c = q * *a;
+                }
+            }
+            Kernel::Add => {
+                for ((c, a), b) in c.iter_mut().zip(a).zip(b) {
+                    *c = *a + *b;
+                }
+            }
+            Kernel::Triad => {
+                for ((c, a), b) in c.iter_mut().zip(a).zip(b) {
+                    *c = b.mul_add(q, *a);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// one kernel at one working-set size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub kernel: Kernel,
+    /// bytes of all arrays the kernel touches
+    pub bytes: usize,
+    /// bytes/s at the median
+    pub bandwidth: f64,
+}
+
+/// Times `kernel` on arrays totalling about `bytes`, at least one `Cluster`
+/// each.
+pub fn measure(kernel: Kernel, config: &Config, bytes: usize) -> Point {
+    let n = (bytes / kernel.bytes_per_element()).max(1);
+    let a = vec![Cluster::splat(1.0); n];
+    let b = vec![Cluster::splat(2.0); if kernel.inputs() > 1 { n } else { 0 }];
+    let mut c = vec![Cluster::splat(0.0); n];
+    let m = harness::run(config, || {
+        kernel.run(&a, if b.is_empty() { &a } else { &b }, &mut c);
+        black_box(&mut c);
+    });
+    Point {
+        kernel,
+        bytes: n * kernel.bytes_per_element(),
+        bandwidth: (n * kernel.bytes_per_element()) as f64 / m.median() * 1e9,
+    }
+}
+
+/// working sets of [`sweep`]: two per doubling from 4K to the memory
+/// working set of `topology`
+pub fn sizes(topology: &CacheTopology) -> Vec<usize> {
+    geometric(4 << 10, topology.working_set(Target::Memory), 2)
+}
+
+/// every kernel at every size
+pub fn sweep(config: &Config, sizes: &[usize]) -> Vec<Point> {
+    Kernel::ALL
+        .into_iter()
+        .flat_map(|kernel| {
+            sizes
+                .iter()
+                .map(move |&bytes| measure(kernel, config, bytes))
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------------------
+
+/// the sustainable bandwidth where a working set lives
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    pub target: Target,
+    /// the kernel reaching it
+    pub kernel: Kernel,
+    /// bytes/s
+    pub bandwidth: f64,
+}
+
+/// Which level a working set of `bytes` is held against: a data cache if it
+/// fills at most half of it and more than the level below, memory beyond
+/// twice the last level. `None` in between, where it only partly fits.
+pub fn band(bytes: usize, topology: &CacheTopology) -> Option<Target> {
+    let mut below = 0;
+    for cache in (1..).map_while(|level| topology.data_cache(level)) {
+        if bytes <= below {
+            return None;
+        }
+        if bytes <= cache.size / 2 {
+            return Some(Target::Level(cache.level));
+        }
+        below = cache.size;
+    }
+    (bytes > 2 * below).then_some(Target::Memory)
+}
+
+/// The best point of every level of `topology` and of memory, in that order.
+/// Levels without points in their [`band`] are left out.
+pub fn peaks(points: &[Point], topology: &CacheTopology) -> Vec<Peak> {
+    let levels = (1..).map_while(|level| topology.data_cache(level));
+    levels
+        .map(|cache| Target::Level(cache.level))
+        .chain([Target::Memory])
+        .filter_map(|target| {
+            let best = points
+                .iter()
+                .filter(|p| band(p.bytes, topology) == Some(target))
+                .max_by(|a, b| a.bandwidth.total_cmp(&b.bandwidth))?;
+            Some(Peak {
+                target,
+                kernel: best.kernel,
+                bandwidth: best.bandwidth,
+            })
+        })
+        .collect()
+}
+
+/// [`peaks`] of every kernel at the [`working set`](CacheTopology::working_set)
+/// of every level and of memory only, much quicker than a full [`sweep`]
+pub fn calibrate(config: &Config, topology: &CacheTopology) -> Vec<Peak> {
+    let levels = (1..).map_while(|level| topology.data_cache(level));
+    let sizes: Vec<usize> = levels
+        .map(|cache| Target::Level(cache.level))
+        .chain([Target::Memory])
+        .map(|target| topology.working_set(target))
+        .collect();
+    peaks(&sweep(config, &sizes), topology)
+}
+
+/// the bandwidth of `target` in `peaks`
+pub fn peak(peaks: &[Peak], target: Target) -> Option<f64> {
+    peaks
+        .iter()
+        .find(|p| p.target == target)
+        .map(|p| p.bandwidth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::{CacheKind, CacheLevel};
+
+    fn topology() -> CacheTopology {
+        let level = |level, size| CacheLevel {
+            level,
+            kind: CacheKind::Data,
+            size,
+            line_size: 64,
+            ways: 4,
+            sets: size / 256,
+            shared_cpus: vec![0],
+        };
+        CacheTopology {
+            caches: vec![level(1, 4 << 10), level(2, 32 << 10)],
+        }
+    }
+
+    #[test]
+    fn test_kernels() {
+        let a = vec![Cluster::splat(1.0); 4];
+        let b = vec![Cluster::splat(2.0); 4];
+        let mut c = vec![Cluster::splat(0.0); 4];
+        for (kernel, expected) in [
+            (Kernel::Copy, 1.0),
+            (Kernel::Scale, 3.0),
+            (Kernel::Add, 3.0),
+            (Kernel::Triad, 7.0),
+        ] {
+            kernel.run(&a, &b, &mut c);
+            assert_eq!(c, vec![Cluster::splat(expected); 4], "{kernel}");
+        }
+        assert_eq!(Kernel::Triad.bytes_per_element(), 96);
+    }
+
+    #[test]
+    fn test_band() {
+        let topology = topology();
+        assert_eq!(band(1 << 10, &topology), Some(Target::Level(1)));
+        assert_eq!(band(2 << 10, &topology), Some(Target::Level(1)));
+        assert_eq!(band(3 << 10, &topology), None);
+        assert_eq!(band(8 << 10, &topology), Some(Target::Level(2)));
+        assert_eq!(band(40 << 10, &topology), None);
+        assert_eq!(band(80 << 10, &topology), Some(Target::Memory));
+    }
+
+    #[test]
+    fn test_peaks() {
+        let point = |kernel, bytes, bandwidth| Point {
+            kernel,
+            bytes,
+            bandwidth,
+        };
+        let points = [
+            point(Kernel::Copy, 1 << 10, 50.0),
+            point(Kernel::Triad, 2 << 10, 60.0),
+            point(Kernel::Copy, 3 << 10, 99.0),
+            point(Kernel::Add, 80 << 10, 10.0),
+        ];
+        let peaks = peaks(&points, &topology());
+        assert_eq!(
+            peaks,
+            [
+                Peak {
+                    target: Target::Level(1),
+                    kernel: Kernel::Triad,
+                    bandwidth: 60.0
+                },
+                Peak {
+                    target: Target::Memory,
+                    kernel: Kernel::Add,
+                    bandwidth: 10.0
+                },
+            ]
+        );
+        assert_eq!(peak(&peaks, Target::Memory), Some(10.0));
+        assert_eq!(peak(&peaks, Target::Level(2)), None);
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let peaks = calibrate(&Config::quick(), &topology());
+        let targets: Vec<Target> = peaks.iter().map(|p| p.target).collect();
+        assert_eq!(
+            targets,
+            [Target::Level(1), Target::Level(2), Target::Memory]
+        );
+        assert!(peaks.iter().all(|p| p.bandwidth > 0.0));
+    }
+}
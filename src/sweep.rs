@@ -0,0 +1,233 @@
+use std::{fmt, io};
+
+use crate::{
+    harness::{self, Config, Rotation},
+    layout::{compute, make_layout, Layout},
+    report::{self, Meta, Record},
+    topology::{CacheTopology, Target},
+    utils::{make_n_datasets, Cluster},
+};
+
+/// Working-set sizes to run a benchmark at.
+///
+/// The working set of one point is everything the rotation over `m` datasets
+/// touches: `m * n` elements plus the result buffer.
+pub struct SweepConfig {
+    /// smallest working set in bytes
+    pub min_bytes: usize,
+    /// largest working set in bytes
+    pub max_bytes: usize,
+    /// working-set sizes per doubling
+    pub steps_per_octave: usize,
+    /// dataset counts, one series each
+    pub datasets: Vec<usize>,
+    /// timing of every point
+    pub harness: Config,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        SweepConfig {
+            min_bytes: 4 << 10,
+            max_bytes: 256 << 20,
+            steps_per_octave: 2,
+            datasets: vec![1, 4, 16, 64],
+            harness: Config {
+                rotation: Rotation::Sequential,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl SweepConfig {
+    /// the default sweep, extended up to several times the last-level cache
+    pub fn for_topology(topology: &CacheTopology) -> Self {
+        SweepConfig {
+            max_bytes: topology.working_set(Target::Memory),
+            ..Default::default()
+        }
+    }
+}
+
+pub struct Point {
+    /// elements per dataset
+    pub n: usize,
+    /// number of datasets
+    pub m: usize,
+    /// working set in bytes
+    pub bytes: usize,
+    pub ns_per_iter: f64,
+}
+
+/// the points of one benchmark at a fixed dataset count
+pub struct Series {
+    pub name: String,
+    pub m: usize,
+    pub points: Vec<Point>,
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} (m = {})", self.name, self.m)?;
+        writeln!(
+            f,
+            "{:>10} {:>12} {:>14} {:>10}",
+            "n", "bytes", "ns/iter", "ns/elem"
+        )?;
+        for p in &self.points {
+            writeln!(
+                f,
+                "{:>10} {:>12} {:>14.1} {:>10.3}",
+                p.n,
+                p.bytes,
+                p.ns_per_iter,
+                p.ns_per_iter / p.n as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `min, min * 2^(1/steps), ... <= max`, rounded and deduplicated. Panics
+/// unless `min` and `steps_per_octave` are positive.
+pub fn geometric(min: usize, max: usize, steps_per_octave: usize) -> Vec<usize> {
+    assert!(
+        min > 0 && steps_per_octave > 0,
+        "geometric sizes from {min} with {steps_per_octave} steps per octave"
+    );
+    let factor = 2f64.powf(1.0 / steps_per_octave as f64);
+    let mut sizes = Vec::new();
+    let mut x = min as f64;
+    while x.round() as usize <= max {
+        let size = x.round() as usize;
+        if sizes.last() != Some(&size) {
+            sizes.push(size);
+        }
+        x *= factor;
+    }
+    sizes
+}
+
+/// `(n, m)` pairs of the sweep for elements of `bytes_per_element` bytes.
+/// Panics on an empty element or a dataset count of 0.
+pub fn points(config: &SweepConfig, bytes_per_element: usize) -> Vec<(usize, usize)> {
+    assert!(bytes_per_element > 0, "elements of 0 bytes");
+    let mut points = Vec::new();
+    for &m in &config.datasets {
+        assert!(m > 0, "sweep over 0 datasets");
+        let mut last_n = 0;
+        for bytes in geometric(config.min_bytes, config.max_bytes, config.steps_per_octave) {
+            let n = bytes / (m * bytes_per_element + size_of::<Cluster>());
+            if n > last_n {
+                points.push((n, m));
+                last_n = n;
+            }
+        }
+    }
+    points
+}
+
+/// Runs `run` over every point of the sweep, rotating over the datasets built
+/// by `make(seed, n)` as configured. Every point is [emitted](report::emit)
+/// as a record of `meta` with its `n` and `m`. Returns one series per dataset
+/// count.
+pub fn sweep<T>(
+    meta: &Meta,
+    config: &SweepConfig,
+    bytes_per_element: usize,
+    make: impl Fn(usize, usize) -> T,
+    run: impl Fn(&T, &mut [Cluster]) -> Cluster,
+) -> io::Result<Vec<Series>> {
+    let mut series: Vec<Series> = Vec::new();
+    for (n, m) in points(config, bytes_per_element) {
+        let data_sets = make_n_datasets(m, |seed| make(seed, n));
+        let mut result = vec![Cluster::splat(0.0); n];
+        let measurement = harness::run(
+            &config.harness,
+            harness::rotate(&data_sets, &config.harness.rotation, |data_set| {
+                run(data_set, &mut result)
+            }),
+        );
+        let point = Point {
+            n,
+            m,
+            bytes: n * (m * bytes_per_element + size_of::<Cluster>()),
+            ns_per_iter: measurement.median(),
+        };
+        report::emit(&Record::new(
+            Meta {
+                n,
+                m,
+                elements: n,
+                rotation: config.harness.rotation.to_string(),
+                ..meta.clone()
+            },
+            &measurement,
+        ))?;
+        match series.last_mut() {
+            Some(s) if s.m == m => s.points.push(point),
+            _ => series.push(Series {
+                name: meta.name.clone(),
+                m,
+                points: vec![point],
+            }),
+        }
+    }
+    Ok(series)
+}
+
+/// [`sweep`] of the [`compute`] kernel over `fields` of layout `L`
+pub fn sweep_layout<L: Layout<F>, const F: usize, const K: usize>(
+    name: &str,
+    config: &SweepConfig,
+    fields: &[usize; K],
+) -> io::Result<Vec<Series>> {
+    let meta = Meta {
+        name: name.to_string(),
+        ..Meta::layout::<L, F>(fields, 0, 0)
+    };
+    sweep(
+        &meta,
+        config,
+        F * size_of::<Cluster>(),
+        make_layout::<L, F>,
+        |data_set, result| compute(data_set, fields, result),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geometric() {
+        assert_eq!(geometric(4, 64, 1), vec![4, 8, 16, 32, 64]);
+        assert_eq!(geometric(4, 16, 2), vec![4, 6, 8, 11, 16]);
+        assert_eq!(geometric(1, 2, 4), vec![1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "geometric sizes from 0")]
+    fn test_geometric_zero() {
+        geometric(0, 64, 2);
+    }
+
+    #[test]
+    fn test_points() {
+        let config = SweepConfig {
+            min_bytes: 1 << 12,
+            max_bytes: 1 << 20,
+            steps_per_octave: 1,
+            datasets: vec![1, 16],
+            ..Default::default()
+        };
+        let points = points(&config, 9 * size_of::<Cluster>());
+        assert_eq!(points.first(), Some(&(12, 1)));
+        assert_eq!(points.last(), Some(&(225, 16)));
+        for (n, m) in points {
+            let bytes = n * (m * 9 + 1) * size_of::<Cluster>();
+            assert!(bytes <= config.max_bytes);
+        }
+    }
+}
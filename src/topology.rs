@@ -0,0 +1,417 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// root of the per-cpu cache descriptions in sysfs
+pub const SYSFS_CPU: &str = "/sys/devices/system/cpu";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheLevel {
+    pub level: u32,
+    pub kind: CacheKind,
+    /// capacity in bytes
+    pub size: usize,
+    pub line_size: usize,
+    pub ways: usize,
+    pub sets: usize,
+    /// cpus sharing this cache
+    pub shared_cpus: Vec<usize>,
+}
+
+/// where the working set of a benchmark should live
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// fits into the data cache of this level, but not into the level below
+    Level(u32),
+    /// exceeds the last-level cache several times
+    Memory,
+}
+
+/// `l1`, `l2`, ..., `mem`
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Level(level) => write!(f, "l{level}"),
+            Target::Memory => f.write_str("mem"),
+        }
+    }
+}
+
+/// The distinct caches of the machine, as described by
+/// `/sys/devices/system/cpu/cpu*/cache/index*`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheTopology {
+    pub caches: Vec<CacheLevel>,
+}
+
+impl CacheTopology {
+    pub fn detect() -> io::Result<Self> {
+        Self::from_sysfs(Path::new(SYSFS_CPU))
+    }
+
+    /// [`CacheTopology::detect`], or [`CacheTopology::fallback`] where sysfs
+    /// is not available, saying so on stderr
+    pub fn detect_or_fallback() -> Self {
+        Self::detect().unwrap_or_else(|e| {
+            eprintln!("cache topology not detected ({e}), assuming 32K L1D, 1M L2, 32M L3");
+            Self::fallback()
+        })
+    }
+
+    /// a typical desktop core: 32K L1D, 1M L2, 32M L3
+    pub fn fallback() -> Self {
+        let level = |level, size, ways| CacheLevel {
+            level,
+            kind: if level == 1 {
+                CacheKind::Data
+            } else {
+                CacheKind::Unified
+            },
+            size,
+            line_size: 64,
+            ways,
+            sets: size / 64 / ways,
+            shared_cpus: vec![0],
+        };
+        CacheTopology {
+            caches: vec![
+                level(1, 32 << 10, 8),
+                level(2, 1 << 20, 16),
+                level(3, 32 << 20, 16),
+            ],
+        }
+    }
+
+    /// Reads `cpu*/cache/index*` below `cpu_dir`. Caches shared by several
+    /// cpus are listed once. Unreadable caches are skipped and counted on
+    /// stderr, the others are kept.
+    pub fn from_sysfs(cpu_dir: &Path) -> io::Result<Self> {
+        let mut caches: Vec<CacheLevel> = Vec::new();
+        let mut skipped = Vec::new();
+        for cpu in sorted_entries(cpu_dir, "cpu")? {
+            let cache_dir = cpu.join("cache");
+            if !cache_dir.is_dir() {
+                continue;
+            }
+            for index in sorted_entries(&cache_dir, "index")? {
+                match read_cache(&index) {
+                    Ok(cache) if !caches.contains(&cache) => caches.push(cache),
+                    Ok(_) => {}
+                    Err(e) => skipped.push(e),
+                }
+            }
+        }
+        if let Some(e) = skipped.first() {
+            eprintln!(
+                "skipped {} cache descriptions, the first: {e}",
+                skipped.len()
+            );
+        }
+        if caches.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no caches described below {}", cpu_dir.display()),
+            ));
+        }
+        caches.sort_by_key(|c| (c.level, c.kind as u8, c.shared_cpus.first().copied()));
+        Ok(CacheTopology { caches })
+    }
+
+    /// The data or unified cache of `level`. On machines with different core
+    /// types the smallest instance is returned.
+    pub fn data_cache(&self, level: u32) -> Option<&CacheLevel> {
+        self.caches
+            .iter()
+            .filter(|c| c.level == level && c.kind != CacheKind::Instruction)
+            .min_by_key(|c| c.size)
+    }
+
+    pub fn last_level(&self) -> Option<&CacheLevel> {
+        let level = self.caches.iter().map(|c| c.level).max()?;
+        self.data_cache(level)
+    }
+
+    pub fn line_size(&self) -> usize {
+        self.data_cache(1).map_or(64, |c| c.line_size)
+    }
+
+    /// total bytes a rotation over all datasets should touch for `target`
+    pub fn working_set(&self, target: Target) -> usize {
+        let llc = self.last_level().map_or(32 << 20, |c| c.size);
+        match target {
+            Target::Level(level) => self.data_cache(level).map_or(llc, |c| c.size) / 2,
+            Target::Memory => 4 * llc,
+        }
+    }
+
+    /// elements per dataset such that a single dataset fills the
+    /// [`working set`](Self::working_set) of `target`
+    pub fn elements_for(&self, target: Target, bytes_per_element: usize) -> usize {
+        (self.working_set(target) / bytes_per_element).max(1)
+    }
+
+    /// Number of datasets of `n` elements such that rotating over all of them
+    /// fills the [`working set`](Self::working_set) of `target`: as many as
+    /// fit in it for a cache level, at least one, and enough to exceed it for
+    /// memory.
+    pub fn datasets_for(&self, target: Target, n: usize, bytes_per_element: usize) -> usize {
+        let dataset = (n * bytes_per_element).max(1);
+        match target {
+            Target::Level(_) => self.working_set(target) / dataset,
+            Target::Memory => self.working_set(target).div_ceil(dataset),
+        }
+        .max(1)
+    }
+
+    /// `(n, m)` for datasets of at most `max_n` elements targeting `target`:
+    /// `n` is reduced until one dataset fits the working set, `m` fills it
+    pub fn size_for(
+        &self,
+        target: Target,
+        max_n: usize,
+        bytes_per_element: usize,
+    ) -> (usize, usize) {
+        let n = match target {
+            Target::Level(_) => max_n.min(self.elements_for(target, bytes_per_element)),
+            Target::Memory => max_n,
+        };
+        (n, self.datasets_for(target, n, bytes_per_element))
+    }
+}
+
+/// entries of `dir` named `prefix` followed by a number, in numeric order
+fn sorted_entries(dir: &Path, prefix: &str) -> io::Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(number) = name.to_str().and_then(|n| n.strip_prefix(prefix)) else {
+            continue;
+        };
+        if let Ok(number) = number.parse::<usize>() {
+            entries.push((number, entry.path()));
+        }
+    }
+    entries.sort();
+    Ok(entries.into_iter().map(|(_, path)| path).collect())
+}
+
+fn read_cache(index: &Path) -> io::Result<CacheLevel> {
+    let read = |name: &str| -> io::Result<String> {
+        let path = index.join(name);
+        let s = fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        Ok(s.trim().to_string())
+    };
+    let number = |name: &str| -> io::Result<usize> {
+        let s = read(name)?;
+        s.parse().map_err(|_| invalid(index, name, &s))
+    };
+    let kind = match read("type")?.as_str() {
+        "Data" => CacheKind::Data,
+        "Instruction" => CacheKind::Instruction,
+        "Unified" => CacheKind::Unified,
+        s => return Err(invalid(index, "type", s)),
+    };
+    let size = read("size")?;
+    let size = parse_size(&size).ok_or_else(|| invalid(index, "size", &size))?;
+    let line_size = number("coherency_line_size")?;
+    let lines = size / line_size.max(1);
+    // either is derived from the other where missing, 0 ways meaning fully
+    // associative
+    let (ways, sets) = match (number("ways_of_associativity"), number("number_of_sets")) {
+        (Ok(ways), Ok(sets)) => (ways, sets),
+        (Ok(0), Err(_)) => (0, 1),
+        (Ok(ways), Err(_)) => (ways, (lines / ways).max(1)),
+        (Err(_), Ok(sets)) => ((lines / sets.max(1)).max(1), sets),
+        (Err(e), Err(_)) => return Err(e),
+    };
+    let shared = read("shared_cpu_list")?;
+    Ok(CacheLevel {
+        level: number("level")? as u32,
+        kind,
+        size,
+        line_size,
+        ways,
+        sets,
+        shared_cpus: parse_cpu_list(&shared)
+            .ok_or_else(|| invalid(index, "shared_cpu_list", &shared))?,
+    })
+}
+
+fn invalid(index: &Path, name: &str, value: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}/{name}: unexpected value {value:?}", index.display()),
+    )
+}
+
+/// `"48K"`, `"2M"` or plain bytes
+pub fn parse_size(s: &str) -> Option<usize> {
+    let (digits, unit) = match s.as_bytes().last()? {
+        b'K' => (&s[..s.len() - 1], 1 << 10),
+        b'M' => (&s[..s.len() - 1], 1 << 20),
+        b'G' => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    Some(digits.parse::<usize>().ok()? * unit)
+}
+
+/// `"0-3,8,10-11"`
+pub fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in s.split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_index(cpu_dir: &Path, cpu: usize, index: usize, values: [&str; 7]) {
+        let dir = cpu_dir.join(format!("cpu{cpu}/cache/index{index}"));
+        fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "level",
+            "type",
+            "size",
+            "coherency_line_size",
+            "ways_of_associativity",
+            "number_of_sets",
+            "shared_cpu_list",
+        ];
+        for (name, value) in names.iter().zip(values) {
+            fs::write(dir.join(name), format!("{value}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_from_sysfs() {
+        let cpu_dir =
+            std::env::temp_dir().join(format!("cache_bench_sysfs_{}", std::process::id()));
+        for cpu in 0..2 {
+            let own = cpu.to_string();
+            write_index(
+                &cpu_dir,
+                cpu,
+                0,
+                ["1", "Data", "48K", "64", "12", "64", &own],
+            );
+            write_index(
+                &cpu_dir,
+                cpu,
+                1,
+                ["1", "Instruction", "32K", "64", "8", "64", &own],
+            );
+            write_index(
+                &cpu_dir,
+                cpu,
+                2,
+                ["2", "Unified", "2048K", "64", "16", "2048", &own],
+            );
+            write_index(
+                &cpu_dir,
+                cpu,
+                3,
+                ["3", "Unified", "30M", "64", "12", "40960", "0-1"],
+            );
+        }
+        fs::create_dir_all(cpu_dir.join("cpufreq")).unwrap();
+        let topology = CacheTopology::from_sysfs(&cpu_dir);
+        fs::remove_dir_all(&cpu_dir).unwrap();
+        let topology = topology.unwrap();
+
+        assert_eq!(topology.caches.len(), 7);
+        let l1 = topology.data_cache(1).unwrap();
+        assert_eq!((l1.size, l1.ways, l1.sets), (48 << 10, 12, 64));
+        assert_eq!(l1.kind, CacheKind::Data);
+        let llc = topology.last_level().unwrap();
+        assert_eq!(
+            (llc.level, llc.size, llc.shared_cpus.len()),
+            (3, 30 << 20, 2)
+        );
+        assert_eq!(topology.line_size(), 64);
+    }
+
+    #[test]
+    fn test_from_sysfs_partial() {
+        let cpu_dir =
+            std::env::temp_dir().join(format!("cache_bench_partial_{}", std::process::id()));
+        write_index(&cpu_dir, 0, 0, ["1", "Data", "48K", "64", "12", "64", "0"]);
+        write_index(
+            &cpu_dir,
+            0,
+            1,
+            ["2", "Unified", "2048K", "64", "16", "2048", "0"],
+        );
+        write_index(
+            &cpu_dir,
+            0,
+            2,
+            ["3", "Unified", "30M", "64", "12", "40960", "0"],
+        );
+        let index = |i: usize| cpu_dir.join(format!("cpu0/cache/index{i}"));
+        fs::remove_file(index(0).join("number_of_sets")).unwrap();
+        fs::remove_file(index(1).join("ways_of_associativity")).unwrap();
+        fs::remove_file(index(2).join("ways_of_associativity")).unwrap();
+        fs::remove_file(index(2).join("number_of_sets")).unwrap();
+        let topology = CacheTopology::from_sysfs(&cpu_dir);
+        fs::remove_dir_all(&cpu_dir).unwrap();
+        let topology = topology.unwrap();
+
+        // the L3 without either is skipped, the others derived
+        assert_eq!(topology.caches.len(), 2);
+        let l1 = topology.data_cache(1).unwrap();
+        assert_eq!((l1.ways, l1.sets), (12, 64));
+        let l2 = topology.data_cache(2).unwrap();
+        assert_eq!((l2.ways, l2.sets), (16, 2048));
+        assert_eq!(topology.last_level(), Some(l2));
+    }
+
+    #[test]
+    fn test_targets() {
+        let topology = CacheTopology::fallback();
+        assert_eq!(topology.working_set(Target::Level(1)), 16 << 10);
+        assert_eq!(topology.working_set(Target::Memory), 128 << 20);
+        assert_eq!(
+            topology.elements_for(Target::Level(2), 288),
+            (512 << 10) / 288
+        );
+        let m = topology.datasets_for(Target::Memory, 256, 288);
+        assert!(m * 256 * 288 >= 128 << 20);
+        assert!((m - 1) * 256 * 288 < 128 << 20);
+        // the rotation stays within the working set of a level
+        assert_eq!(topology.size_for(Target::Level(1), 256, 288), (56, 1));
+        assert_eq!(topology.size_for(Target::Level(2), 256, 288), (256, 7));
+        assert!(7 * 256 * 288 <= topology.working_set(Target::Level(2)));
+        assert_eq!(topology.size_for(Target::Memory, 256, 288), (256, m));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_size("48K"), Some(48 << 10));
+        assert_eq!(parse_size("107520K"), Some(105 << 20));
+        assert_eq!(parse_size("2M"), Some(2 << 20));
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list("a"), None);
+    }
+}
@@ -0,0 +1,306 @@
+//! Memory access traces of the kernels: every load and store of a `Cluster`
+//! as address, size and direction.
+//!
+//! The kernels read and write through [`read`] and [`write`], which record
+//! the access while [`capture`] runs on the same thread. Recording is only
+//! compiled in with the `trace` feature; without it both are plain loads and
+//! stores and traces come back empty.
+//!
+//! Traces are written as text, one `R|W ADDRESS SIZE` line per access, or
+//! compactly as [`MAGIC`] followed by 12 bytes per access: the address as
+//! little endian `u64`, the size as little endian `u32` with the top bit set
+//! for writes. For offline simulators they are also written in the Dinero
+//! `din` format, see [`write_din`].
+
+use std::{
+    fmt,
+    io::{self, BufRead, Read, Write},
+};
+
+pub use crate::cachesim::Access;
+
+/// first bytes of a binary trace
+pub const MAGIC: &[u8; 8] = b"CBTRACE1";
+
+/// top bit of the size of a binary write
+const WRITE_BIT: u32 = 1 << 31;
+
+#[cfg(feature = "trace")]
+thread_local! {
+    static TRACE: std::cell::RefCell<Option<Vec<Access>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// adds `access` to the trace being captured on this thread, if any
+#[inline(always)]
+pub fn record(access: Access) {
+    #[cfg(feature = "trace")]
+    TRACE.with_borrow_mut(|trace| {
+        if let Some(trace) = trace {
+            trace.push(access);
+        }
+    });
+    #[cfg(not(feature = "trace"))]
+    let _ = access;
+}
+
+/// `*r`, recorded as a load
+#[inline(always)]
+pub fn read<T: Copy>(r: &T) -> T {
+    record(Access::read(r as *const T as usize, size_of::<T>()));
+    *r
+}
+
+/// `p.read_unaligned()`, recorded as a load
+///
+/// # Safety
+///
+/// As for [`std::ptr::read_unaligned`].
+#[inline(always)]
+pub unsafe fn read_unaligned<T: Copy>(p: *const T) -> T {
+    record(Access::read(p as usize, size_of::<T>()));
+    // SAFETY: up to the caller
+    unsafe { p.read_unaligned() }
+}
+
+/// `*r = v`, recorded as a store
+#[inline(always)]
+pub fn write<T>(r: &mut T, v: T) {
+    record(Access::write(r as *const T as usize, size_of::<T>()));
+    *r = v;
+}
+
+/// true if [`capture`] records anything in this build
+pub const fn enabled() -> bool {
+    cfg!(feature = "trace")
+}
+
+/// Runs `f` and returns the accesses it made through [`read`] and [`write`]
+/// on this thread, in order. Empty without the `trace` feature.
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, Vec<Access>) {
+    #[cfg(feature = "trace")]
+    {
+        let outer = TRACE.replace(Some(Vec::new()));
+        let r = f();
+        let trace = TRACE.replace(outer).unwrap_or_default();
+        (r, trace)
+    }
+    #[cfg(not(feature = "trace"))]
+    (f(), Vec::new())
+}
+
+// ----------------------------------------------------------------------------
+
+/// file format of a written trace
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// [`write_din`]
+    #[default]
+    Din,
+    /// [`write_text`]
+    Text,
+    /// [`write_binary`]
+    Binary,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "din" => Some(Format::Din),
+            "text" => Some(Format::Text),
+            "binary" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+
+    /// file extension, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Din => "din",
+            Format::Text => "trace",
+            Format::Binary => "bin",
+        }
+    }
+
+    pub fn write(self, w: &mut impl Write, trace: &[Access]) -> io::Result<()> {
+        match self {
+            Format::Din => write_din(w, trace),
+            Format::Text => write_text(w, trace),
+            Format::Binary => write_binary(w, trace),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Din => "din",
+            Format::Text => "text",
+            Format::Binary => "binary",
+        })
+    }
+}
+
+/// Dinero `din` label of a data read
+pub const DIN_READ: u8 = 0;
+/// Dinero `din` label of a data write
+pub const DIN_WRITE: u8 = 1;
+
+/// Writes the classic Dinero `din` format, one `LABEL ADDRESS` line per
+/// access with the address in hex, as read by `dineroIV -informat d`. The
+/// format has no sizes, every access is one reference at its first byte.
+pub fn write_din(w: &mut impl Write, trace: &[Access]) -> io::Result<()> {
+    for a in trace {
+        let label = if a.write { DIN_WRITE } else { DIN_READ };
+        writeln!(w, "{label} {:x}", a.addr)?;
+    }
+    Ok(())
+}
+
+pub fn write_text(w: &mut impl Write, trace: &[Access]) -> io::Result<()> {
+    for a in trace {
+        let kind = if a.write { 'W' } else { 'R' };
+        writeln!(w, "{kind} {:#018x} {}", a.addr, a.size)?;
+    }
+    Ok(())
+}
+
+/// Reads [`write_text`] output, skipping blank lines.
+pub fn read_text(r: impl BufRead) -> io::Result<Vec<Access>> {
+    let mut trace = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: not an access: {line:?}", i + 1),
+            )
+        };
+        let mut parts = line.split_whitespace();
+        let write = match parts.next() {
+            Some("R") => false,
+            Some("W") => true,
+            _ => return Err(invalid()),
+        };
+        let addr = parts
+            .next()
+            .and_then(|a| a.strip_prefix("0x"))
+            .and_then(|a| usize::from_str_radix(a, 16).ok())
+            .ok_or_else(invalid)?;
+        let size = parts
+            .next()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        trace.push(Access { addr, size, write });
+    }
+    Ok(trace)
+}
+
+pub fn write_binary(w: &mut impl Write, trace: &[Access]) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    for a in trace {
+        let size = u32::try_from(a.size)
+            .ok()
+            .filter(|&s| s < WRITE_BIT)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "access too large"))?;
+        w.write_all(&(a.addr as u64).to_le_bytes())?;
+        w.write_all(&(size | if a.write { WRITE_BIT } else { 0 }).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_binary(mut r: impl Read) -> io::Result<Vec<Access>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    let body = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| invalid("not a binary trace"))?;
+    if body.len() % 12 != 0 {
+        return Err(invalid("truncated binary trace"));
+    }
+    Ok(body
+        .chunks_exact(12)
+        .map(|c| {
+            let addr = u64::from_le_bytes(c[..8].try_into().unwrap());
+            let size = u32::from_le_bytes(c[8..].try_into().unwrap());
+            Access {
+                addr: addr as usize,
+                size: (size & !WRITE_BIT) as usize,
+                write: size & WRITE_BIT != 0,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::{compute, compute_accesses, make_layout, Blob, SPARSE_9},
+        utils::Cluster,
+    };
+
+    fn trace() -> Vec<Access> {
+        vec![
+            Access::read(0x1000, 32),
+            Access::read(0x7fff_0000_0040, 32),
+            Access::write(0x2000, 8),
+        ]
+    }
+
+    #[test]
+    fn test_text() {
+        let mut out = Vec::new();
+        write_text(&mut out, &trace()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("R 0x0000000000001000 32\n"));
+        assert_eq!(read_text(text.as_bytes()).unwrap(), trace());
+        let err = read_text(&b"R 0x10\n"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: not an access: \"R 0x10\"");
+    }
+
+    #[test]
+    fn test_din() {
+        let mut out = Vec::new();
+        write_din(&mut out, &trace()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "0 1000\n0 7fff00000040\n1 2000\n"
+        );
+        for format in [Format::Din, Format::Text, Format::Binary] {
+            assert_eq!(Format::parse(&format.to_string()), Some(format));
+        }
+        assert_eq!(Format::parse("dinero"), None);
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut out = Vec::new();
+        write_binary(&mut out, &trace()).unwrap();
+        assert_eq!(out.len(), 8 + 3 * 12);
+        assert_eq!(read_binary(&out[..]).unwrap(), trace());
+        assert!(read_binary(&out[..out.len() - 1]).is_err());
+        assert!(read_binary(&b"CBTRACE0"[..]).is_err());
+    }
+
+    #[test]
+    fn test_capture() {
+        let data_set: Blob<9> = make_layout(0, 4);
+        let mut result = vec![Cluster::splat(0.0); 4];
+        let (sum, trace) = capture(|| compute(&data_set, &SPARSE_9, &mut result));
+        assert_eq!(sum, compute(&data_set, &SPARSE_9, &mut result));
+        if enabled() {
+            let mut expected = Vec::new();
+            compute_accesses(&data_set, &SPARSE_9, &result, |a| expected.push(a));
+            assert_eq!(trace, expected);
+        } else {
+            assert!(trace.is_empty());
+        }
+    }
+}
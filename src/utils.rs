@@ -1,8 +1,7 @@
-use std::simd::Simd;
+use std::{ops::Range, slice};
 
-use test::Bencher;
-
-pub type Cluster = Simd<f32, 8>;
+pub use crate::simd::Cluster;
+use crate::{aligned::AlignedBuf, cache::Footprint};
 
 /// number of datasets
 pub const M: usize = 300;
@@ -24,6 +23,14 @@ pub struct Data {
     pub e: Vec<Cluster>,
 }
 
+impl Footprint for Data {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        for column in [&self.a, &self.b, &self.c, &self.d, &self.e] {
+            column.ranges(out);
+        }
+    }
+}
+
 pub fn make_data() -> Data {
     Data {
         b: vec![Cluster::splat(1.0); N],
@@ -34,29 +41,139 @@ pub fn make_data() -> Data {
     }
 }
 
-pub fn make_datasets<T>(f: impl Fn(usize) -> T) -> Vec<T> {
-    let mut v = Vec::with_capacity(M);
-    for seed in 0..M {
-        v.push(f(seed));
+/// the columns of a dataset the runtime kernels read and write
+pub trait Columns: Footprint {
+    fn a(&self) -> &[Cluster];
+    fn c(&self) -> &[Cluster];
+    fn d(&self) -> &[Cluster];
+    fn e(&self) -> &[Cluster];
+    fn b_mut(&mut self) -> &mut [Cluster];
+}
+
+impl Columns for Data {
+    fn a(&self) -> &[Cluster] {
+        &self.a
+    }
+
+    fn c(&self) -> &[Cluster] {
+        &self.c
+    }
+
+    fn d(&self) -> &[Cluster] {
+        &self.d
+    }
+
+    fn e(&self) -> &[Cluster] {
+        &self.e
+    }
+
+    fn b_mut(&mut self) -> &mut [Cluster] {
+        &mut self.b
     }
-    v
 }
 
-#[inline(always)]
-pub fn bench(
-    b: &mut Bencher,
-    data_sets_in: &[Data],
-    data_sets_out: &mut [Data],
-    mut f: impl FnMut(&Data, &mut Data),
-) {
-    let mut i = 0;
-    b.iter(|| {
-        let data_set_in = &data_sets_in[i];
-        let data_set_out = &mut data_sets_out[i];
-        f(data_set_in, data_set_out);
-        i += 7;
-        if i >= N {
-            i -= N;
+/// bytes of a page, the period of 4K aliasing
+pub const PAGE: usize = 4096;
+
+/// bytes between the columns of [`PlacedData`], whole pages
+const PLACED_STRIDE: usize = (N * size_of::<Cluster>()).next_multiple_of(PAGE);
+
+/// [`Data`] in one page-aligned allocation, every column starting the same
+/// `offset` bytes past a page boundary
+pub struct PlacedData {
+    buf: AlignedBuf,
+    offset: usize,
+}
+
+impl PlacedData {
+    /// column `k` of `a`, `b`, `c`, `d`, `e`
+    fn column(&self, k: usize) -> &[Cluster] {
+        // SAFETY: the column is within buf, aligned and initialized in
+        // make_placed_data
+        unsafe {
+            slice::from_raw_parts(
+                self.buf
+                    .as_ptr()
+                    .add(self.offset + k * PLACED_STRIDE)
+                    .cast(),
+                N,
+            )
         }
-    });
+    }
+
+    /// where every column starts past a page boundary
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Footprint for PlacedData {
+    fn ranges(&self, out: &mut Vec<Range<usize>>) {
+        for k in 0..5 {
+            self.column(k).ranges(out);
+        }
+    }
+}
+
+impl Columns for PlacedData {
+    fn a(&self) -> &[Cluster] {
+        self.column(0)
+    }
+
+    fn c(&self) -> &[Cluster] {
+        self.column(2)
+    }
+
+    fn d(&self) -> &[Cluster] {
+        self.column(3)
+    }
+
+    fn e(&self) -> &[Cluster] {
+        self.column(4)
+    }
+
+    fn b_mut(&mut self) -> &mut [Cluster] {
+        // SAFETY: as in column, and borrowed mutably through self
+        unsafe {
+            slice::from_raw_parts_mut(
+                self.buf
+                    .as_mut_ptr()
+                    .add(self.offset + PLACED_STRIDE)
+                    .cast(),
+                N,
+            )
+        }
+    }
+}
+
+/// The values of [`make_data`], placed `offset` bytes past a page boundary.
+/// Panics unless `offset` is below [`PAGE`] and aligned for a `Cluster`.
+pub fn make_placed_data(offset: usize) -> PlacedData {
+    assert!(
+        offset < PAGE && offset.is_multiple_of(align_of::<Cluster>()),
+        "invalid column offset {offset}"
+    );
+    let mut buf = AlignedBuf::new(offset + 5 * PLACED_STRIDE, PAGE);
+    for (k, v) in [4.0, 1.0, 3.0, 2.0, 5.0].into_iter().enumerate() {
+        // SAFETY: the N Clusters of column k are within buf and aligned
+        unsafe {
+            let column: *mut Cluster = buf.as_mut_ptr().add(offset + k * PLACED_STRIDE).cast();
+            for i in 0..N {
+                column.add(i).write(Cluster::splat(v));
+            }
+        }
+    }
+    PlacedData { buf, offset }
+}
+
+pub fn make_datasets<T>(f: impl Fn(usize) -> T) -> Vec<T> {
+    make_n_datasets(M, f)
+}
+
+pub fn make_n_datasets<T>(m: usize, f: impl Fn(usize) -> T) -> Vec<T> {
+    let mut v = Vec::with_capacity(m);
+    for seed in 0..m {
+        v.push(f(seed));
+    }
+    v
 }